@@ -2,4 +2,4 @@
 mod sunburst;
 
 // pub use chart_size::ChartSize;
-pub use sunburst::SunburstChart;
+pub use sunburst::{SunburstChart, Weighting};
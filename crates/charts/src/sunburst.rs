@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 use charming::{
     element::{Emphasis, EmphasisFocus, ItemStyle, Label, LabelAlign, LabelPosition, Sort},
@@ -17,49 +17,59 @@ pub struct SunburstChart {
     // size: ChartSize,
 }
 
-impl SunburstChart {
-    pub fn new(change_requests: Vec<ChangeRequest>) -> Self {
-        let mut category_map: HashMap<String, HashMap<String, Vec<&ChangeRequest>>> =
-            HashMap::new();
+/// How a change request with several tags counts under each of them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Weighting {
+    /// Once under every tag, so the rings add up to more than the notes.
+    #[default]
+    Full,
+    /// A share of one split evenly between its tags.
+    Fractional,
+}
 
-        // Group change requests by category and subcategory
+impl SunburstChart {
+    pub fn new(change_requests: Vec<ChangeRequest>, weighting: Weighting) -> Self {
+        let mut root = Branch::default();
         for change in &change_requests {
-            let category = change
-                .category
-                .clone()
-                .unwrap_or_else(|| "Uncategorized".to_string());
-            let sub_category = change
-                .sub_category
-                .clone()
-                .unwrap_or_else(|| "Uncategorized".to_string());
-
-            category_map
-                .entry(category)
-                .or_default()
-                .entry(sub_category)
-                .or_default()
-                .push(change);
-        }
-
-        let mut data = Vec::new();
-        for (category, subcategories) in category_map {
-            let mut category_children = Vec::new();
-
-            for (sub_category, changes) in subcategories {
-                let sub_category_children: Vec<SunburstNode> = changes
-                    .iter()
-                    .map(|change| {
-                        SunburstNode::new(format!("{}/{}", change.merge_request_id, change.id))
-                            .value(1.0)
-                    })
-                    .collect();
-
-                category_children
-                    .push(SunburstNode::new(sub_category).children(sub_category_children));
+            let value = match weighting {
+                Weighting::Full => 1.0,
+                Weighting::Fractional => 1.0 / change.tags.len().max(1) as f64,
+            };
+            if change.tags.is_empty() {
+                root.insert(&["Uncategorized".to_string()], (change, value));
+            }
+            for tag in &change.tags {
+                root.insert(&tag.path, (change, value));
             }
+        }
 
-            data.push(SunburstNode::new(category).children(category_children));
+        // One ring per level of the deepest tag, between the center and the
+        // outer ring of change requests. Change requests tagged less deeply
+        // than that sit on an inner ring, so their border is set on the nodes
+        // without children rather than by level.
+        let depth = root.depth().max(1);
+        let width = 55.0 / depth as f64;
+        let mut levels = vec![SunburstLevel::new()];
+        for level in 0..depth {
+            let r0 = 15.0 + width * level as f64;
+            let ring = SunburstLevel::new()
+                .r0(format!("{}%", r0).as_str())
+                .r(format!("{}%", r0 + width).as_str());
+            levels.push(if level == 0 {
+                ring.item_style(ItemStyle::new().border_width(2))
+                    .label(Label::new().rotate("tangential"))
+            } else {
+                ring.label(Label::new().align(LabelAlign::Right))
+            });
         }
+        levels.push(
+            SunburstLevel::new().r0("70%").r("72%").label(
+                Label::new()
+                    .position(LabelPosition::Outside)
+                    .padding((3, 3, 3, 3))
+                    .silent(false),
+            ),
+        );
 
         SunburstChart {
             chart: Chart::new().series(
@@ -67,29 +77,8 @@ impl SunburstChart {
                     .radius(("0%", "95%"))
                     .emphasis(Emphasis::new().focus(EmphasisFocus::Ancestor))
                     .sort(Sort::None)
-                    .levels(vec![
-                        SunburstLevel::new(),
-                        SunburstLevel::new()
-                            .r0("15%")
-                            .r("35%")
-                            .item_style(ItemStyle::new().border_width(2))
-                            .label(Label::new().rotate("tangential")),
-                        SunburstLevel::new()
-                            .r0("35%")
-                            .r("70%")
-                            .label(Label::new().align(LabelAlign::Right)),
-                        SunburstLevel::new()
-                            .r0("70%")
-                            .r("72%")
-                            .item_style(ItemStyle::new().border_width(3))
-                            .label(
-                                Label::new()
-                                    .position(LabelPosition::Outside)
-                                    .padding((3, 3, 3, 3))
-                                    .silent(false),
-                            ),
-                    ])
-                    .data(data),
+                    .levels(levels)
+                    .data(root.into_nodes()),
             ),
             // on_click: None,
             // size: ChartSize::fullscreen().unwrap(),
@@ -164,6 +153,47 @@ impl SunburstChart {
     }
 }
 
+/// A level of the tag tree: the tags below it, and the change requests
+/// tagged with it and nothing deeper.
+#[derive(Default)]
+struct Branch<'a> {
+    branches: BTreeMap<String, Branch<'a>>,
+    leaves: Vec<(&'a ChangeRequest, f64)>,
+}
+
+impl<'a> Branch<'a> {
+    fn insert(&mut self, path: &[String], leaf: (&'a ChangeRequest, f64)) {
+        match path.split_first() {
+            Some((name, path)) => self
+                .branches
+                .entry(name.clone())
+                .or_default()
+                .insert(path, leaf),
+            None => self.leaves.push(leaf),
+        }
+    }
+
+    fn depth(&self) -> usize {
+        self.branches
+            .values()
+            .map(|branch| branch.depth() + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn into_nodes(self) -> Vec<SunburstNode> {
+        self.branches
+            .into_iter()
+            .map(|(name, branch)| SunburstNode::new(name).children(branch.into_nodes()))
+            .chain(self.leaves.into_iter().map(|(change, value)| {
+                SunburstNode::new(format!("{}/{}", change.merge_request_id, change.id))
+                    .value(value)
+                    .item_style(ItemStyle::new().border_width(3))
+            }))
+            .collect()
+    }
+}
+
 #[wasm_bindgen]
 pub struct Size {
     pub height: u32,
@@ -1,98 +1,256 @@
+use std::collections::BTreeMap;
+
 use convert_case::{Case, Casing};
-use openai_dive::v1::{
-    api::Client,
-    models::Gpt4Engine,
-    resources::chat::{
-        ChatCompletionParametersBuilder, ChatCompletionResponseFormat, ChatMessage,
-        ChatMessageContent, JsonSchemaBuilder,
-    },
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    change_request::{ChangeRequest, Tag},
+    error::Error,
+    scheduler::Scheduler,
+    taxonomy::{Taxonomy, TaxonomyEntry},
 };
 
-use crate::{change_request::ChangeRequest, parsed_note::ParsedNote};
+/// Categorizes change requests through the OpenAI chat completions API,
+/// picking among the categories of a [`Taxonomy`] that are not deprecated.
+///
+/// The API is called directly rather than through an SDK, so that requests
+/// go through a [`Scheduler`] like those of the forge clients: retried when
+/// they fail transiently, paused when the rate limit is spent, and sent from
+/// the browser as well.
+#[derive(Clone)]
+pub struct AiClient {
+    api_key: String,
+    base_url: String,
+    model: String,
+    scheduler: Scheduler,
+    taxonomy: Taxonomy,
+}
+
+#[derive(Deserialize)]
+struct Categorized {
+    category: String,
+    sub_category: String,
+}
 
-pub struct AiClient(Client);
+#[derive(Deserialize)]
+struct Completion {
+    choices: Vec<Choice>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: Message,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    content: Option<String>,
+}
 
 impl AiClient {
-    pub fn new() -> Self {
-        Self(Client::new_from_env())
+    pub fn new(api_key: String, taxonomy: Taxonomy) -> Self {
+        Self {
+            api_key,
+            base_url: "https://api.openai.com/v1".to_string(),
+            model: "gpt-4o".to_string(),
+            scheduler: Scheduler::default(),
+            taxonomy,
+        }
     }
 
-    pub async fn categorize(&self, change_request: &mut ChangeRequest) {
-        let instructions = r#"
-            You are a helpful assistant that categorizes a change request.
-            Categories and sub-categories must be in kebab-case.
-
-            Possible categories:
-                - effect: Anything that is related to the Effect-ts library.
-                - oversight: A human error like a typo or a missing word.
-                - domain: A misrepresentation of the domain or an error in the business logic.
-                - complicated: A complicated function or data-structure that is hard to understand.
-                - performance: A performance issue that can be solved by optimizing the code.
-                - security: A security issue that can be solved by fixing the code.
-                - documentation: A documentation issue that can be solved by fixing the code.
-                - testing: A testing issue that can be solved by fixing the code.
-                - other: Any other category that does not fit into the above categories.
-            "#;
-
-        let client = self.0.clone();
-
-        let parameters = ChatCompletionParametersBuilder::default()
-            .model(Gpt4Engine::Gpt4O.to_string())
-            .messages(vec![
-                ChatMessage::System {
-                    content: ChatMessageContent::Text(instructions.to_string()),
-                    name: None,
-                },
-                ChatMessage::User {
-                    content: ChatMessageContent::Text(change_request.description.clone()),
-                    name: None,
-                },
-            ])
-            .response_format(ChatCompletionResponseFormat::JsonSchema(
-                JsonSchemaBuilder::default()
-                    .name("categories")
-                    .schema(serde_json::json!({
-                        "type": "object",
-                        "properties": {
-                            "description": { "type": "string" },
-                            "category": {
-                                "type": "string",
-                                "enum": [
-                                    "effect",
-                                    "oversight",
-                                    "domain",
-                                    "complicated",
-                                    "performance",
-                                    "security",
-                                    "documentation",
-                                    "testing",
-                                    "other"
-                                ]
+    /// Sends requests to another OpenAI-compatible API.
+    pub fn with_base_url(mut self, base_url: String) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_model(mut self, model: String) -> Self {
+        self.model = model;
+        self
+    }
+
+    pub async fn categorize(&self, change_request: &mut ChangeRequest) -> Result<(), Error> {
+        let request = reqwest::Client::new()
+            .post(format!("{}/chat/completions", self.base_url))
+            .bearer_auth(&self.api_key)
+            .json(&json!({
+                "model": self.model,
+                "messages": [
+                    { "role": "system", "content": self.instructions() },
+                    { "role": "user", "content": change_request.description },
+                ],
+                "response_format": {
+                    "type": "json_schema",
+                    "json_schema": {
+                        "name": "categories",
+                        "strict": true,
+                        "schema": {
+                            "type": "object",
+                            "properties": {
+                                "description": { "type": "string" },
+                                "category": {
+                                    "type": "string",
+                                    "enum": active(&self.taxonomy.categories)
+                                        .map(|(name, _)| name)
+                                        .collect::<Vec<_>>(),
+                                },
+                                "sub_category": { "type": "string" },
                             },
-                            "sub_category": { "type": "string" },
+                            "required": ["description", "category", "sub_category"],
+                            "additionalProperties": false
                         },
-                        "required": ["description", "category", "sub_category"],
-                        "additionalProperties": false
-                    }))
-                    .strict(true)
-                    .build()
-                    .unwrap(),
-            ))
-            .build()
-            .unwrap();
-
-        let result = client.chat().create(parameters).await.unwrap();
-
-        let message = &result.choices.first().unwrap().message;
-
-        if let ChatMessage::Assistant {
-            content: Some(ChatMessageContent::Text(message)),
-            ..
-        } = message
-        {
-            let parsed_note: ParsedNote = serde_json::from_str(&message).unwrap();
-            change_request.category = parsed_note.category.map(|v| v.to_case(Case::Kebab));
-            change_request.sub_category = parsed_note.sub_category.map(|v| v.to_case(Case::Kebab));
+                    },
+                },
+            }));
+        let completion: Completion = self.scheduler.send(request).await?.json().await?;
+
+        let message = completion
+            .choices
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::Decode("no choice in completion".to_string()))?
+            .message;
+
+        if let Some(message) = message.content {
+            let categorized: Categorized = serde_json::from_str(&message)?;
+            let tag = Tag {
+                path: [categorized.category, categorized.sub_category]
+                    .into_iter()
+                    .map(|level| level.to_case(Case::Kebab))
+                    .filter(|level| !level.is_empty())
+                    .collect(),
+            };
+            change_request.tags = self.taxonomy.normalize(vec![tag.clone()]);
+            change_request.original_tags = vec![tag];
         }
+
+        Ok(())
+    }
+
+    /// Lists the categories and sub-categories to choose from.
+    fn instructions(&self) -> String {
+        let mut instructions = "You are a helpful assistant that categorizes a change request.
+Categories and sub-categories must be in kebab-case. Prefer the sub-categories
+listed under a category, if any.
+
+Possible categories:
+"
+        .to_string();
+        let line = |name: String, entry: &TaxonomyEntry| match &entry.description {
+            Some(description) => format!("{}: {}\n", name, description),
+            None => format!("{}\n", name),
+        };
+        for (name, entry) in active(&self.taxonomy.categories) {
+            instructions += &format!("- {}", line(name.clone(), entry));
+            for (sub_name, sub_entry) in active(&entry.sub_categories) {
+                instructions +=
+                    &format!("  - {}", line(format!("{}/{}", name, sub_name), sub_entry));
+            }
+        }
+        instructions
+    }
+}
+
+/// The entries that are not deprecated.
+fn active(
+    entries: &BTreeMap<String, TaxonomyEntry>,
+) -> impl Iterator<Item = (&String, &TaxonomyEntry)> {
+    entries.iter().filter(|(_, entry)| !entry.deprecated)
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{body_partial_json, header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::test_support::change_request;
+
+    #[tokio::test]
+    async fn test_categorize() {
+        let server = MockServer::start().await;
+        let content = json!({
+            "description": "The token ends up in the logs",
+            "category": "security",
+            "sub_category": "sqli",
+        });
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .and(header("authorization", "Bearer key"))
+            .and(body_partial_json(json!({
+                "response_format": { "json_schema": { "schema": { "properties": {
+                    "category": { "type": "string", "enum": ["security", "testing"] },
+                } } } },
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "choices": [{ "message": { "content": content.to_string() } }],
+            })))
+            .mount(&server)
+            .await;
+
+        let taxonomy = Taxonomy::from_toml(
+            r#"
+            version = 1
+
+            [categories.security]
+            description = "Weaknesses an attacker could use"
+
+            [categories.security.sub_categories.injection]
+            aliases = ["sqli"]
+
+            [categories.testing]
+
+            [categories.oversight]
+            deprecated = true
+            "#,
+        )
+        .unwrap();
+        let client = AiClient::new("key".to_string(), taxonomy).with_base_url(server.uri());
+        assert!(client.instructions().ends_with(
+            "- security: Weaknesses an attacker could use\n  - security/injection\n- testing\n"
+        ));
+
+        let mut change_request = change_request(100, 7, "The query is built by hand");
+        client.categorize(&mut change_request).await.unwrap();
+        assert!(change_request.tags == vec![Tag::from("security/injection")]);
+        assert!(change_request.original_tags == vec![Tag::from("security/sqli")]);
+    }
+
+    #[tokio::test]
+    async fn test_categorize_errors() {
+        let server = MockServer::start().await;
+        let client = AiClient::new("key".to_string(), Taxonomy::default())
+            .with_base_url(server.uri())
+            .with_model("gpt-4o-mini".to_string());
+        let respond = |response: ResponseTemplate| {
+            Mock::given(method("POST"))
+                .and(path("/chat/completions"))
+                .and(body_partial_json(json!({ "model": "gpt-4o-mini" })))
+                .respond_with(response)
+                .up_to_n_times(1)
+        };
+        let mut change_request = change_request(100, 7, "The query is built by hand");
+
+        respond(ResponseTemplate::new(401)).mount(&server).await;
+        let result = client.categorize(&mut change_request).await;
+        assert!(matches!(result, Err(Error::Auth)));
+
+        respond(ResponseTemplate::new(200).set_body_json(json!({ "choices": [] })))
+            .mount(&server)
+            .await;
+        let result = client.categorize(&mut change_request).await;
+        assert!(matches!(result, Err(Error::Decode(_))));
+
+        // A refusal comes without content and leaves the tags alone.
+        respond(ResponseTemplate::new(200).set_body_json(json!({
+            "choices": [{ "message": { "content": null, "refusal": "No" } }],
+        })))
+        .mount(&server)
+        .await;
+        client.categorize(&mut change_request).await.unwrap();
+        assert!(change_request.tags.is_empty());
     }
 }
@@ -0,0 +1,102 @@
+//! Keeps a file cache of change requests up to date from GitLab webhooks.
+//!
+//! Configured from the same variables as the UI, plus `GITLAB_WEBHOOK_SECRET`,
+//! `WEBHOOK_ADDRESS` (defaults to `127.0.0.1:3000`), `REVIEWER_CACHE`, the
+//! directory of the cache (defaults to `cache`), and optionally
+//! `REVIEWER_TAXONOMY`, a `.toml` or `.json` taxonomy to normalize tags to.
+//! The tags out of line with the taxonomy are listed on startup. Logs go to
+//! stderr, at the level set by `RUST_LOG` (defaults to `info`).
+
+use std::{env, path::Path};
+
+use client::{
+    store::FileStore, GitlabClient, ReviewSource, Reviewers, Scope, Taxonomy, WebhookReceiver,
+};
+use log::*;
+use tokio::net::TcpListener;
+
+fn var(name: &str) -> String {
+    env::var(name).unwrap_or_else(|_| panic!("{} must be set", name))
+}
+
+/// Writes every record to stderr.
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!(
+                "{:<5} {}: {}",
+                record.level(),
+                record.target(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn main() -> std::io::Result<()> {
+    let level = env::var("RUST_LOG")
+        .ok()
+        .and_then(|level| level.parse().ok())
+        .unwrap_or(LevelFilter::Info);
+    set_logger(&Logger).expect("no other logger is set");
+    set_max_level(level);
+
+    let mut client = GitlabClient::new(
+        var("GITLAB_URL"),
+        var("GITLAB_ACCESS_TOKEN"),
+        var("GITLAB_PROJECT").parse::<Scope>().unwrap(),
+        var("GITLAB_REVIEWERS").parse::<Reviewers>().unwrap(),
+        FileStore::new(env::var("REVIEWER_CACHE").unwrap_or_else(|_| "cache".to_string())),
+    );
+    let taxonomy = env::var("REVIEWER_TAXONOMY")
+        .ok()
+        .map(|path| Taxonomy::read(Path::new(&path)).unwrap());
+    if let Some(taxonomy) = &taxonomy {
+        client = client.with_taxonomy(taxonomy.clone());
+    }
+    let address = env::var("WEBHOOK_ADDRESS").unwrap_or_else(|_| "127.0.0.1:3000".to_string());
+
+    tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .enable_time()
+        .build()?
+        .block_on(async {
+            if let Some(taxonomy) = &taxonomy {
+                report_tag_issues(&client, taxonomy).await;
+            }
+            let receiver = WebhookReceiver::new(client, var("GITLAB_WEBHOOK_SECRET")).on_change(
+                |change_requests| info!("{} change request(s) updated", change_requests.len()),
+            );
+            let listener = TcpListener::bind(address).await?;
+            receiver.serve(listener).await
+        })
+}
+
+/// Lists the unknown and deprecated tags of the change requests in scope,
+/// along with the notes using them.
+async fn report_tag_issues(client: &GitlabClient<FileStore>, taxonomy: &Taxonomy) {
+    let change_requests = match client.fetch().await {
+        Ok(report) => report.change_requests,
+        Err(error) => {
+            error!("Failed to check tags against the taxonomy: {}", error);
+            return;
+        }
+    };
+    let issues = taxonomy.validate(&change_requests);
+    info!(
+        "{} tag(s) out of line with taxonomy version {}",
+        issues.len(),
+        taxonomy.version
+    );
+    for issue in issues {
+        warn!("{}\n  {}", issue, issue.urls.join("\n  "));
+    }
+}
@@ -0,0 +1,451 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use log::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    change_request::ChangeRequest, error::Error, review_source::SyncReport, store::Store,
+    sync_policy::SyncPolicy, taxonomy::Taxonomy,
+};
+
+/// Bumped whenever the cache changes in a way serde cannot decode on its own,
+/// along with a step in [`migrate`]. Fields added to [`ChangeRequest`] take
+/// `#[serde(default)]` instead, so that upgrading does not cost a full resync.
+const VERSION: u32 = 13;
+
+/// Caches older than this predate the timestamps the date filters rely on and
+/// are rebuilt from scratch.
+const OLDEST_VERSION: u32 = 1;
+
+/// The change requests of a source, along with the point up to which they
+/// have been synced.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Cache {
+    pub change_requests: Vec<ChangeRequest>,
+    pub from: DateTime<Utc>,
+    /// When the cache was last synced from scratch.
+    pub rebuilt_at: DateTime<Utc>,
+    /// The [`SyncPolicy::start`] the cache was built with.
+    pub start: DateTime<Utc>,
+    /// The version of the taxonomy the tags were last normalized to.
+    #[serde(default)]
+    pub taxonomy: Option<u32>,
+    #[serde(default)]
+    pub version: u32,
+}
+
+/// What a fetch has to do given the cache and the sync policy.
+pub(crate) enum Plan {
+    /// The cache is recent enough to be served as is.
+    Cached(Cache),
+    /// Merge requests updated after `since` have to be synced into `cache`.
+    Fetch { cache: Cache, since: DateTime<Utc> },
+}
+
+impl Cache {
+    pub fn new(policy: &SyncPolicy, now: DateTime<Utc>) -> Self {
+        Self {
+            change_requests: vec![],
+            from: policy.start,
+            rebuilt_at: now,
+            start: policy.start,
+            taxonomy: None,
+            version: VERSION,
+        }
+    }
+
+    /// Decides whether `cache` can be served, synced incrementally or has to
+    /// be rebuilt from scratch under `policy`.
+    pub fn plan(cache: Option<Self>, policy: &SyncPolicy, now: DateTime<Utc>) -> Plan {
+        let cache = cache.filter(|cache| {
+            let expired = policy
+                .full_resync_every
+                .is_some_and(|every| now - cache.rebuilt_at >= duration(every));
+            if cache.start != policy.start || expired {
+                info!("Rebuilding cache synced since {}", cache.start);
+                return false;
+            }
+            true
+        });
+
+        match cache {
+            Some(cache) if now < cache.from + duration(policy.ttl) => Plan::Cached(cache),
+            Some(cache) => Plan::Fetch {
+                since: (cache.from - duration(policy.backfill)).max(cache.start),
+                cache,
+            },
+            None => Plan::Fetch {
+                since: policy.start,
+                cache: Cache::new(policy, now),
+            },
+        }
+    }
+
+    /// Reads the cache under `key`, migrating it if it was written by an older
+    /// version and discarding it if it can no longer be decoded. Tags normalized to another
+    /// version of `taxonomy` are normalized again from the notes as written.
+    pub async fn load(
+        store: &impl Store,
+        key: &str,
+        taxonomy: Option<&Taxonomy>,
+    ) -> Result<Option<Self>, Error> {
+        let Some(value) = store.load(key).await? else {
+            return Ok(None);
+        };
+        let mut value: serde_json::Value = match serde_json::from_str(&value) {
+            Ok(value) => value,
+            Err(error) => {
+                warn!("Discarding unreadable cache: {}", error);
+                return Ok(None);
+            }
+        };
+        let version = value["version"].as_u64().unwrap_or_default();
+        if !(u64::from(OLDEST_VERSION)..=u64::from(VERSION)).contains(&version) {
+            info!("Rebuilding cache {} written by version {}", key, version);
+            return Ok(None);
+        }
+        if version < u64::from(VERSION) {
+            info!("Migrating cache {} written by version {}", key, version);
+            migrate(&mut value, version);
+        }
+        match serde_json::from_value::<Self>(value) {
+            Ok(mut cache) => {
+                if let Some(taxonomy) = taxonomy {
+                    cache.normalize(taxonomy);
+                }
+                Ok(Some(cache))
+            }
+            Err(error) => {
+                warn!("Discarding unreadable cache: {}", error);
+                Ok(None)
+            }
+        }
+    }
+
+    fn normalize(&mut self, taxonomy: &Taxonomy) {
+        if self.taxonomy == Some(taxonomy.version) {
+            return;
+        }
+        info!("Normalizing tags to taxonomy version {}", taxonomy.version);
+        for change_request in &mut self.change_requests {
+            // Notes cached before their tags as written were kept.
+            if change_request.original_tags.is_empty() {
+                change_request.original_tags = change_request.tags.clone();
+            }
+            change_request.tags = taxonomy.normalize(change_request.original_tags.clone());
+        }
+        self.taxonomy = Some(taxonomy.version);
+    }
+
+    pub async fn save(&self, store: &impl Store, key: &str) -> Result<(), Error> {
+        let value =
+            serde_json::to_string(self).map_err(|error| Error::Storage(error.to_string()))?;
+        store.save(key, value).await
+    }
+
+    /// Folds the change requests of each synced merge request into the cache
+    /// and persists it. `now` must be taken before the first request so that
+    /// anything updated during the sync is picked up next time.
+    pub async fn sync(
+        mut self,
+        store: &impl Store,
+        key: &str,
+        now: DateTime<Utc>,
+        results: Vec<(String, u64, Result<Vec<ChangeRequest>, Error>)>,
+    ) -> SyncReport {
+        let mut errors = vec![];
+
+        for (project, merge_request_id, fresh) in results {
+            match fresh {
+                Ok(fresh) => self.upsert(&project, merge_request_id, fresh),
+                Err(error) => {
+                    warn!("Failed to sync {}!{}: {}", project, merge_request_id, error);
+                    errors.push(error);
+                }
+            }
+        }
+
+        // Only advance the sync point when every merge request made it, so the
+        // failed ones are retried on the next fetch.
+        if errors.is_empty() {
+            self.from = now;
+        }
+        if let Err(error) = self.save(store, key).await {
+            errors.push(error);
+        }
+
+        SyncReport {
+            change_requests: self.change_requests,
+            errors,
+        }
+    }
+
+    pub fn upsert(&mut self, project: &str, merge_request_id: u64, fresh: Vec<ChangeRequest>) {
+        upsert(&mut self.change_requests, project, merge_request_id, fresh);
+    }
+}
+
+impl From<Cache> for SyncReport {
+    fn from(cache: Cache) -> Self {
+        SyncReport {
+            change_requests: cache.change_requests,
+            errors: vec![],
+        }
+    }
+}
+
+/// Rewrites a cache written by `version` into the current format, one version
+/// at a time.
+fn migrate(value: &mut serde_json::Value, version: u64) {
+    // Version 4 made the sync start configurable, it used to be fixed.
+    if version < 4 {
+        value["rebuilt_at"] = value["from"].clone();
+        value["start"] = json!(SyncPolicy::default().start);
+    }
+    let change_requests = value["change_requests"].as_array_mut();
+    for change_request in change_requests.into_iter().flatten() {
+        // Version 8 replaced the category and sub-category with tags.
+        if version < 8 {
+            change_request["tags"] = match change_request["category"].is_null() {
+                true => json!([]),
+                false => json!([{
+                    "category": change_request["category"],
+                    "sub_category": change_request["sub_category"],
+                }]),
+            };
+        }
+        // Version 9 turned tags into paths of any depth.
+        if version < 9 {
+            let tags = change_request["tags"].as_array_mut();
+            for tag in tags.into_iter().flatten() {
+                let path = [&tag["category"], &tag["sub_category"]]
+                    .into_iter()
+                    .filter(|level| !level.is_null())
+                    .cloned()
+                    .collect::<Vec<_>>();
+                *tag = json!({ "path": path });
+            }
+        }
+        // Version 13 stopped labels from being blocking on their own.
+        if version < 13 && change_request["severity"] == "blocking" {
+            let decorations = &change_request["conventional_comment"]["decorations"];
+            if !decorations
+                .as_array()
+                .is_some_and(|decorations| decorations.contains(&json!("blocking")))
+            {
+                change_request["severity"] = json!("minor");
+            }
+        }
+    }
+    value["version"] = json!(VERSION);
+}
+
+fn duration(duration: std::time::Duration) -> TimeDelta {
+    TimeDelta::from_std(duration).unwrap_or_else(|_| TimeDelta::max_value())
+}
+
+/// Replaces the change requests of a freshly synced merge request: notes are
+/// matched by [`ChangeRequest::key`] and updated in place, new ones are appended and the ones
+/// missing upstream are dropped.
+fn upsert(
+    change_requests: &mut Vec<ChangeRequest>,
+    project: &str,
+    merge_request_id: u64,
+    fresh: Vec<ChangeRequest>,
+) {
+    change_requests.retain(|change_request| {
+        change_request.project != project
+            || change_request.merge_request_id != merge_request_id
+            || fresh
+                .iter()
+                .any(|value| value.key() == change_request.key())
+    });
+
+    for change_request in fresh {
+        match change_requests
+            .iter_mut()
+            .find(|value| value.key() == change_request.key())
+        {
+            Some(value) => *value = change_request,
+            None => change_requests.push(change_request),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        change_request::{NoteKind, Tag},
+        conventional_comment::Severity,
+        store::MemoryStore,
+        test_support::change_request,
+    };
+
+    #[tokio::test]
+    async fn test_load_migrates_older_versions() {
+        let store = MemoryStore::new();
+        let legacy = r#"{"change_requests":[{"id":1}],"from":"2024-11-01T10:00:00Z"}"#;
+        store.save("key", legacy.to_string()).await.unwrap();
+        assert!(Cache::load(&store, "key", None).await.unwrap().is_none());
+
+        let cache = Cache {
+            change_requests: vec![change_request(1, 7, "kept")],
+            ..Cache::new(&SyncPolicy::default(), Utc::now())
+        };
+        cache.save(&store, "key").await.unwrap();
+        let loaded = Cache::load(&store, "key", None).await.unwrap().unwrap();
+        assert!(loaded.change_requests == cache.change_requests);
+
+        // As written by version 1, before any of the fields added since.
+        let old = json!({
+            "change_requests": [{
+                "author": "author",
+                "category": "testing",
+                "created_at": "2024-11-01T10:00:00Z",
+                "description": "migrated",
+                "id": 1,
+                "merge_request_created_at": "2024-11-01T09:00:00Z",
+                "merge_request_id": 7,
+                "merge_request_merged_at": null,
+                "project": "group/project",
+                "reviewer": "reviewer",
+                "sub_category": null,
+                "updated_at": "2024-11-01T10:00:00Z",
+                "url": "url",
+            }],
+            "from": "2024-11-02T10:00:00Z",
+            "version": 1,
+        });
+        store.save("key", old.to_string()).await.unwrap();
+        let loaded = Cache::load(&store, "key", None).await.unwrap().unwrap();
+        assert!(loaded.version == VERSION && loaded.start == SyncPolicy::default().start);
+        assert!(loaded.change_requests[0].description == "migrated");
+        assert!(loaded.change_requests[0].tags == vec![Tag::from("testing")]);
+
+        let mut old = serde_json::to_value(&cache).unwrap();
+        old["version"] = json!(12);
+        old["change_requests"][0]["conventional_comment"] =
+            json!({ "decorations": [], "label": "issue", "subject": "leak" });
+        old["change_requests"][0]["severity"] = json!("blocking");
+        store.save("key", old.to_string()).await.unwrap();
+        let loaded = Cache::load(&store, "key", None).await.unwrap().unwrap();
+        assert!(loaded.change_requests[0].severity == Some(Severity::Minor));
+
+        let newer = json!({ "version": VERSION + 1 });
+        store.save("key", newer.to_string()).await.unwrap();
+        assert!(Cache::load(&store, "key", None).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_load_normalizes_to_the_taxonomy() {
+        let store = MemoryStore::new();
+        let taxonomy = |version, replaced_by| Taxonomy {
+            version,
+            ..Taxonomy::from_toml(&format!(
+                "version = 0\n[categories.oversight]\ndeprecated = true\nreplaced_by = \"{}\"",
+                replaced_by
+            ))
+            .unwrap()
+        };
+        let tags = |cache: Cache| cache.change_requests[0].tags[0].to_string();
+        Cache {
+            change_requests: vec![ChangeRequest {
+                original_tags: vec![Tag::from("oversight")],
+                tags: vec![Tag::from("correctness")],
+                ..change_request(1, 7, "")
+            }],
+            taxonomy: Some(1),
+            ..Cache::new(&SyncPolicy::default(), Utc::now())
+        }
+        .save(&store, "key")
+        .await
+        .unwrap();
+
+        let cache = Cache::load(&store, "key", Some(&taxonomy(1, "logic")))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(tags(cache) == "correctness");
+        let cache = Cache::load(&store, "key", Some(&taxonomy(2, "logic")))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(cache.taxonomy == Some(2) && tags(cache) == "logic");
+    }
+
+    #[test]
+    fn test_plan() {
+        let policy = SyncPolicy {
+            backfill: Duration::from_secs(60 * 60),
+            full_resync_every: Some(Duration::from_secs(7 * 24 * 60 * 60)),
+            ..SyncPolicy::default()
+        };
+        let now = "2024-11-10T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let cache = |from: &str, rebuilt_at: &str| Cache {
+            from: from.parse().unwrap(),
+            rebuilt_at: rebuilt_at.parse().unwrap(),
+            ..Cache::new(&policy, now)
+        };
+        let since = |plan| match plan {
+            Plan::Cached(_) => None,
+            Plan::Fetch { since, .. } => Some(since.to_rfc3339()),
+        };
+
+        let fresh = cache("2024-11-10T11:58:00Z", "2024-11-09T12:00:00Z");
+        assert!(since(Cache::plan(Some(fresh), &policy, now)).is_none());
+
+        let stale = cache("2024-11-10T11:00:00Z", "2024-11-09T12:00:00Z");
+        assert!(
+            since(Cache::plan(Some(stale.clone()), &policy, now)).as_deref()
+                == Some("2024-11-10T10:00:00+00:00")
+        );
+
+        let moved = SyncPolicy {
+            start: "2024-01-01T00:00:00Z".parse().unwrap(),
+            ..policy.clone()
+        };
+        assert!(
+            since(Cache::plan(Some(stale), &moved, now)).as_deref()
+                == Some("2024-01-01T00:00:00+00:00")
+        );
+
+        let old = cache("2024-11-10T11:00:00Z", "2024-11-01T12:00:00Z");
+        assert!(
+            since(Cache::plan(Some(old), &policy, now)).as_deref()
+                == Some("2024-10-28T12:00:00+00:00")
+        );
+    }
+
+    #[test]
+    fn test_upsert() {
+        let mut change_requests = vec![
+            change_request(1, 7, "kept"),
+            change_request(2, 7, "deleted"),
+            change_request(3, 8, "other MR"),
+        ];
+
+        let fresh = vec![change_request(1, 7, "edited"), change_request(4, 7, "new")];
+        upsert(&mut change_requests, "group/project", 7, fresh.clone());
+        let expected = vec![
+            change_request(1, 7, "edited"),
+            change_request(3, 8, "other MR"),
+            change_request(4, 7, "new"),
+        ];
+        assert!(change_requests == expected);
+
+        upsert(&mut change_requests, "group/project", 7, fresh);
+        assert!(change_requests == expected);
+
+        // A review and a review comment may share an id.
+        let review = ChangeRequest {
+            kind: NoteKind::Review,
+            ..change_request(1, 7, "review")
+        };
+        let fresh = vec![change_request(1, 7, "edited"), review.clone()];
+        upsert(&mut change_requests, "group/project", 7, fresh);
+        assert!(change_requests.len() == 3 && change_requests.contains(&review));
+    }
+}
@@ -1,12 +1,409 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::conventional_comment::{ConventionalComment, Severity};
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ChangeRequest {
     pub author: String,
-    pub category: Option<String>,
+    /// Whether the author of the merge request answered in the thread.
+    #[serde(default)]
+    pub author_replied: bool,
+    /// The label and decorations of notes following Conventional Comments.
+    pub conventional_comment: Option<ConventionalComment>,
+    pub created_at: DateTime<Utc>,
     pub description: String,
+    /// Only unique among change requests of the same kind.
     pub id: u64,
+    #[serde(default)]
+    pub kind: NoteKind,
+    #[serde(default)]
+    pub merge_request: MergeRequestMetadata,
+    pub merge_request_created_at: DateTime<Utc>,
     pub merge_request_id: u64,
-    pub sub_category: Option<String>,
+    pub merge_request_merged_at: Option<DateTime<Utc>>,
+    /// The tags as written in the note, before a [`Taxonomy`] normalized them
+    /// into `tags`.
+    ///
+    /// [`Taxonomy`]: crate::taxonomy::Taxonomy
+    #[serde(default)]
+    pub original_tags: Vec<Tag>,
+    /// Where in the diff the note was left, `None` for general comments.
+    pub position: Option<DiffPosition>,
+    pub project: String,
+    /// The award emoji left on the note.
+    #[serde(default)]
+    pub reactions: Vec<Reaction>,
+    /// The number of notes in the thread after the first one.
+    #[serde(default)]
+    pub replies: usize,
+    /// Whether the thread can be resolved at all. Sources that do not expose
+    /// resolution leave this off, along with the other `resolved*` fields.
+    #[serde(default)]
+    pub resolvable: bool,
+    #[serde(default)]
+    pub resolved: bool,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub resolved_by: Option<String>,
+    pub reviewer: String,
+    /// What the label of a conventional comment implies, `None` for other
+    /// notes.
+    pub severity: Option<Severity>,
+    /// The suggestion blocks of the note, in the order they appear in it.
+    #[serde(default)]
+    pub suggestions: Vec<Suggestion>,
+    /// The hashtags ending the note, in the order they appear in it.
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+    pub updated_at: DateTime<Utc>,
     pub url: String,
 }
+
+/// What a change request was read from. GitHub and Gitea number reviews and
+/// review comments separately, so their ids overlap.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteKind {
+    /// A GitLab note, the only kind GitLab has.
+    #[default]
+    Note,
+    /// The body of a pull request review.
+    Review,
+    /// A comment on the diff, left as part of a review.
+    ReviewComment,
+}
+
+/// A hashtag classifying a change request, from the broadest level down,
+/// e.g. `#security/injection/sql`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Tag {
+    pub path: Vec<String>,
+}
+
+impl Tag {
+    /// The first level of the tag.
+    pub fn category(&self) -> &str {
+        self.path.first().map_or("", String::as_str)
+    }
+}
+
+impl From<&str> for Tag {
+    fn from(str: &str) -> Self {
+        Tag {
+            path: str
+                .split('/')
+                .filter(|level| !level.is_empty())
+                .map(ToString::to_string)
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path.join("/"))
+    }
+}
+
+/// An award emoji on the note of a change request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Reaction {
+    /// The name of the emoji, e.g. `thumbsup`.
+    pub name: String,
+    pub user: String,
+}
+
+/// How the team reacted to change requests: 👍 counts as agreeing with the
+/// feedback and 👎 as disputing it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReactionStats {
+    pub agreed: usize,
+    pub disputed: usize,
+}
+
+impl ReactionStats {
+    pub fn of(change_request: &ChangeRequest) -> Self {
+        let count = |name| {
+            change_request
+                .reactions
+                .iter()
+                .filter(|reaction| reaction.name == name)
+                .count()
+        };
+        Self {
+            agreed: count("thumbsup"),
+            disputed: count("thumbsdown"),
+        }
+    }
+
+    /// Sums the reactions per tag, most disputed first. Change requests count
+    /// under each of their tags, those without any are left out.
+    pub fn by_tag(change_requests: &[ChangeRequest]) -> Vec<(String, Self)> {
+        let mut stats = BTreeMap::<_, Self>::new();
+        for change_request in change_requests {
+            let reactions = Self::of(change_request);
+            for tag in change_request.tags.iter().collect::<BTreeSet<_>>() {
+                let stats = stats.entry(tag.to_string()).or_default();
+                stats.agreed += reactions.agreed;
+                stats.disputed += reactions.disputed;
+            }
+        }
+
+        let mut stats = stats.into_iter().collect::<Vec<_>>();
+        stats.sort_by(|(_, a), (_, b)| b.disputed.cmp(&a.disputed).then(a.agreed.cmp(&b.agreed)));
+        stats
+    }
+}
+
+/// A ```` ```suggestion ```` block: code proposed to replace the commented
+/// lines, widened by `lines_above` and `lines_below`, e.g. ```` ```suggestion:-1+2 ````.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Suggestion {
+    /// `None` when the source does not report whether it was applied. GitLab
+    /// exposes it neither through REST nor GraphQL, so it is always `None`
+    /// for GitLab notes.
+    pub applied: Option<bool>,
+    pub code: String,
+    pub lines_above: u64,
+    pub lines_below: u64,
+}
+
+/// How many suggestions were applied, out of those whose outcome is known.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SuggestionStats {
+    pub applied: usize,
+    pub total: usize,
+}
+
+impl SuggestionStats {
+    /// Groups the suggestions of `change_requests` by category, `None`
+    /// standing for change requests without one. Change requests tagged with
+    /// several categories count under each.
+    pub fn by_category(change_requests: &[ChangeRequest]) -> BTreeMap<Option<String>, Self> {
+        let mut stats = BTreeMap::<_, Self>::new();
+        for change_request in change_requests {
+            let mut categories = change_request
+                .tags
+                .iter()
+                .map(|tag| Some(tag.category().to_string()))
+                .collect::<BTreeSet<_>>();
+            if categories.is_empty() {
+                categories.insert(None);
+            }
+            for applied in change_request
+                .suggestions
+                .iter()
+                .filter_map(|value| value.applied)
+            {
+                for category in &categories {
+                    let stats = stats.entry(category.clone()).or_default();
+                    stats.applied += usize::from(applied);
+                    stats.total += 1;
+                }
+            }
+        }
+        stats
+    }
+}
+
+impl fmt::Display for SuggestionStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.applied, self.total)
+    }
+}
+
+/// The merge request a change request was left on, as of its last sync.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MergeRequestMetadata {
+    /// `None` when the source does not report the size of the diff.
+    pub diff_stats: Option<DiffStats>,
+    pub draft: bool,
+    pub labels: Vec<String>,
+    pub state: MergeRequestState,
+    pub target_branch: String,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeRequestState {
+    #[default]
+    #[serde(alias = "open")]
+    Opened,
+    Merged,
+    Closed,
+    Locked,
+}
+
+impl fmt::Display for MergeRequestState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeRequestState::Opened => write!(f, "opened"),
+            MergeRequestState::Merged => write!(f, "merged"),
+            MergeRequestState::Closed => write!(f, "closed"),
+            MergeRequestState::Locked => write!(f, "locked"),
+        }
+    }
+}
+
+/// How much a merge request changes, summed over its files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffStats {
+    pub additions: u64,
+    pub deletions: u64,
+    pub files: u64,
+}
+
+impl DiffStats {
+    /// Buckets the number of changed lines, so that merge requests of a
+    /// similar size can be compared.
+    pub fn size(&self) -> Size {
+        match self.additions + self.deletions {
+            0..10 => Size::XS,
+            10..30 => Size::S,
+            30..100 => Size::M,
+            100..500 => Size::L,
+            _ => Size::XL,
+        }
+    }
+}
+
+/// T-shirt sizes, from under 10 changed lines to 500 and more.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Size {
+    XS,
+    S,
+    M,
+    L,
+    XL,
+}
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// The lines of a diff a review comment is attached to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffPosition {
+    /// The commit the comment was made against.
+    pub commit_sha: String,
+    pub line_end: Option<u64>,
+    pub line_start: Option<u64>,
+    pub new_path: String,
+    pub old_path: String,
+}
+
+impl DiffPosition {
+    /// The directory of the file, empty at the root of the repository.
+    pub fn directory(&self) -> &str {
+        self.new_path
+            .rsplit_once('/')
+            .map_or("", |(directory, _)| directory)
+    }
+
+    /// The extension of the file, a rough stand-in for its language.
+    pub fn extension(&self) -> Option<&str> {
+        let (_, file) = self
+            .new_path
+            .rsplit_once('/')
+            .unwrap_or(("", &self.new_path));
+        // Dotfiles such as `.env` have no extension.
+        file.trim_start_matches('.')
+            .rsplit_once('.')
+            .map(|(_, extension)| extension)
+    }
+}
+
+/// An inclusive range of days, open on either side when a bound is missing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DateRange {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+impl DateRange {
+    pub fn contains(&self, date: DateTime<Utc>) -> bool {
+        let date = date.date_naive();
+        self.from.is_none_or(|from| from <= date) && self.to.is_none_or(|to| date <= to)
+    }
+}
+
+impl ChangeRequest {
+    /// Identifies the change request within its source.
+    pub fn key(&self) -> (NoteKind, u64) {
+        (self.kind, self.id)
+    }
+
+    /// Whether the note was written within `range`.
+    pub fn created_within(&self, range: &DateRange) -> bool {
+        range.contains(self.created_at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_position() {
+        let position = |path: &str| DiffPosition {
+            commit_sha: "abc".to_string(),
+            line_end: None,
+            line_start: None,
+            new_path: path.to_string(),
+            old_path: path.to_string(),
+        };
+
+        assert!(position("src/client/lib.rs").directory() == "src/client");
+        assert!(position("src/client/lib.rs").extension() == Some("rs"));
+        assert!(position("Makefile").directory().is_empty());
+        assert!(position("Makefile").extension().is_none());
+        assert!(position("ui/.env").extension().is_none());
+        assert!(position("ui/.env.local").extension() == Some("local"));
+    }
+
+    #[test]
+    fn test_diff_stats_size() {
+        let stats = |additions, deletions| DiffStats {
+            additions,
+            deletions,
+            files: 1,
+        };
+
+        assert!(stats(0, 0).size() == Size::XS);
+        assert!(stats(6, 4).size() == Size::S);
+        assert!(stats(99, 0).size() == Size::M);
+        assert!(stats(250, 250).size() == Size::XL);
+        assert!(Size::XL.to_string() == "XL");
+    }
+
+    #[test]
+    fn test_date_range_contains() {
+        let date = "2024-11-01T23:30:00Z".parse().unwrap();
+        let day = |value: &str| Some(value.parse::<NaiveDate>().unwrap());
+
+        assert!(DateRange::default().contains(date));
+        assert!(DateRange {
+            from: day("2024-11-01"),
+            to: day("2024-11-01"),
+        }
+        .contains(date));
+        assert!(!DateRange {
+            from: day("2024-11-02"),
+            to: None,
+        }
+        .contains(date));
+        assert!(!DateRange {
+            from: None,
+            to: day("2024-10-31"),
+        }
+        .contains(date));
+    }
+}
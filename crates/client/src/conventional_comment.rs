@@ -0,0 +1,184 @@
+use std::{fmt, str::FromStr};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// A note following [Conventional Comments](https://conventionalcomments.org),
+/// e.g. `issue (blocking, security): the token is logged`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConventionalComment {
+    /// The words in parentheses after the label, lowercased.
+    pub decorations: Vec<String>,
+    pub label: CommentLabel,
+    pub subject: String,
+}
+
+impl ConventionalComment {
+    /// Reads the first line of `description`, which may be in bold as in
+    /// `**nitpick (non-blocking):** …`.
+    pub fn parse(description: &str) -> Option<Self> {
+        let re = Regex::new(
+            r"^(?:\*\*|__)?([A-Za-z]+)(?:\s*\(([^)]*)\))?(?:\*\*|__)?:(?:\*\*|__)?\s*(.*)$",
+        )
+        .unwrap();
+        let line = description.trim_start().lines().next()?.trim();
+        let caps = re.captures(line)?;
+
+        let label = caps[1].parse().ok()?;
+        let decorations = caps
+            .get(2)
+            .map(|m| {
+                m.as_str()
+                    .split(',')
+                    .map(|decoration| decoration.trim().to_lowercase())
+                    .filter(|decoration| !decoration.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Some(ConventionalComment {
+            decorations,
+            label,
+            subject: caps[3].to_string(),
+        })
+    }
+
+    /// Only the `(blocking)` decoration makes a comment blocking, as the
+    /// specification has it. `(non-blocking)` and `(if-minor)` cap what the
+    /// label implies.
+    pub fn severity(&self) -> Severity {
+        let decorated = |decoration: &str| self.decorations.iter().any(|value| value == decoration);
+        if decorated("blocking") {
+            Severity::Blocking
+        } else if decorated("non-blocking") || decorated("if-minor") {
+            self.label.severity().min(Severity::Minor)
+        } else {
+            self.label.severity()
+        }
+    }
+}
+
+/// The labels of the specification, plus the common `nit` for `nitpick`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CommentLabel {
+    Chore,
+    Issue,
+    Nitpick,
+    Note,
+    Polish,
+    Praise,
+    Question,
+    Quibble,
+    Suggestion,
+    Thought,
+    Todo,
+    Typo,
+}
+
+impl CommentLabel {
+    pub const ALL: [CommentLabel; 12] = [
+        CommentLabel::Chore,
+        CommentLabel::Issue,
+        CommentLabel::Nitpick,
+        CommentLabel::Note,
+        CommentLabel::Polish,
+        CommentLabel::Praise,
+        CommentLabel::Question,
+        CommentLabel::Quibble,
+        CommentLabel::Suggestion,
+        CommentLabel::Thought,
+        CommentLabel::Todo,
+        CommentLabel::Typo,
+    ];
+
+    /// How much the label weighs when no decoration says otherwise. No label
+    /// is blocking on its own, not even `issue`, `chore` or `todo`.
+    pub fn severity(self) -> Severity {
+        match self {
+            CommentLabel::Chore
+            | CommentLabel::Issue
+            | CommentLabel::Nitpick
+            | CommentLabel::Polish
+            | CommentLabel::Quibble
+            | CommentLabel::Suggestion
+            | CommentLabel::Todo
+            | CommentLabel::Typo => Severity::Minor,
+            CommentLabel::Note | CommentLabel::Question | CommentLabel::Thought => Severity::Info,
+            CommentLabel::Praise => Severity::Praise,
+        }
+    }
+}
+
+impl FromStr for CommentLabel {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        if s == "nit" {
+            return Ok(CommentLabel::Nitpick);
+        }
+        CommentLabel::ALL
+            .into_iter()
+            .find(|label| label.to_string() == s)
+            .ok_or(())
+    }
+}
+
+impl fmt::Display for CommentLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+/// How strongly a change request asks for a change, from least to most.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Praise,
+    Info,
+    Minor,
+    Blocking,
+}
+
+impl Severity {
+    pub fn is_blocking(self) -> bool {
+        self == Severity::Blocking
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let comment = ConventionalComment::parse(
+            "issue (blocking, Security): the token is logged\n\nMask it first.",
+        )
+        .unwrap();
+        assert!(comment.label == CommentLabel::Issue);
+        assert!(comment.decorations == vec!["blocking", "security"]);
+        assert!(comment.subject == "the token is logged");
+
+        let comment = ConventionalComment::parse("**nit:** spacing").unwrap();
+        assert!(comment.label == CommentLabel::Nitpick && comment.decorations.is_empty());
+        assert!(comment.subject == "spacing");
+
+        assert!(ConventionalComment::parse("Why pin this version?").is_none());
+        assert!(ConventionalComment::parse("Changelog: internal").is_none());
+    }
+
+    #[test]
+    fn test_severity() {
+        let severity = |description| ConventionalComment::parse(description).unwrap().severity();
+        assert!(severity("issue: leak") == Severity::Minor);
+        assert!(severity("chore: bump the lockfile") == Severity::Minor);
+        assert!(severity("todo: add a test") == Severity::Minor);
+        assert!(severity("issue (blocking): leak") == Severity::Blocking);
+        assert!(severity("issue (non-blocking): leak") == Severity::Minor);
+        assert!(severity("suggestion (blocking): extract") == Severity::Blocking);
+        assert!(severity("question (non-blocking): why?") == Severity::Info);
+        assert!(severity("praise: neat") == Severity::Praise);
+    }
+}
@@ -0,0 +1,77 @@
+use std::fmt;
+
+use reqwest::{Response, StatusCode};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Error {
+    /// The access token is missing, expired or lacks the required scope.
+    Auth,
+    /// The server throttled us; `retry_after` is in seconds when provided.
+    RateLimit {
+        retry_after: Option<u64>,
+    },
+    Network(String),
+    Decode(String),
+    Storage(String),
+}
+
+impl Error {
+    /// Maps a non-success response onto an error, passing successful ones through.
+    pub(crate) fn check(response: Response) -> Result<Response, Error> {
+        let headers = response.headers();
+        let retry_after = headers
+            .get("retry-after")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok());
+        // GitHub answers 403 rather than 429 once the quota is spent.
+        let exhausted = headers
+            .get("x-ratelimit-remaining")
+            .is_some_and(|value| value == "0");
+
+        match response.status() {
+            StatusCode::TOO_MANY_REQUESTS => Err(Error::RateLimit { retry_after }),
+            StatusCode::FORBIDDEN if exhausted => Err(Error::RateLimit { retry_after }),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(Error::Auth),
+            status if !status.is_success() => Err(Error::Network(format!(
+                "{} responded with {}",
+                response.url(),
+                status
+            ))),
+            _ => Ok(response),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Auth => write!(f, "Authentication failed, check the access token"),
+            Error::RateLimit {
+                retry_after: Some(retry_after),
+            } => write!(f, "Rate limited, retry in {}s", retry_after),
+            Error::RateLimit { retry_after: None } => write!(f, "Rate limited, retry later"),
+            Error::Network(message) => write!(f, "Network error: {}", message),
+            Error::Decode(message) => write!(f, "Unexpected response: {}", message),
+            Error::Storage(message) => write!(f, "Storage error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<reqwest::Error> for Error {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_decode() {
+            Error::Decode(error.to_string())
+        } else {
+            Error::Network(error.to_string())
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::Decode(error.to_string())
+    }
+}
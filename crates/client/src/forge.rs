@@ -0,0 +1,163 @@
+use std::future::Future;
+
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use log::*;
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::{
+    cache::{Cache, Plan},
+    change_request::{ChangeRequest, MergeRequestMetadata, MergeRequestState, NoteKind},
+    error::Error,
+    gitlab_client::Author,
+    pagination::get_while,
+    parsed_note::ParsedNote,
+    review_source::SyncReport,
+    reviewers::Reviewers,
+    scheduler::Scheduler,
+    store::Store,
+    sync_policy::SyncPolicy,
+    taxonomy::Taxonomy,
+};
+
+/// A pull request as listed by GitHub or Gitea.
+pub(crate) trait PullRequest: DeserializeOwned {
+    fn created_at(&self) -> DateTime<Utc>;
+    fn merged_at(&self) -> Option<DateTime<Utc>>;
+    fn metadata(&self) -> MergeRequestMetadata;
+    fn number(&self) -> u64;
+    fn updated_at(&self) -> DateTime<Utc>;
+    fn user(&self) -> &Author;
+}
+
+/// A review or review comment, before it is turned into a change request.
+pub(crate) struct Note {
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    pub id: u64,
+    pub kind: NoteKind,
+    pub updated_at: DateTime<Utc>,
+    pub url: String,
+    pub user: Option<Author>,
+}
+
+/// A GitHub or Gitea repository, along with what its client syncs it with.
+pub(crate) struct Repository<'a, S> {
+    pub key: String,
+    pub name: &'a str,
+    pub reviewers: &'a Reviewers,
+    pub scheduler: &'a Scheduler,
+    pub store: &'a S,
+    pub sync_policy: &'a SyncPolicy,
+    pub taxonomy: Option<&'a Taxonomy>,
+}
+
+impl<S: Store> Repository<'_, S> {
+    /// Returns the cached change requests, syncing the pull requests updated
+    /// since the last sync with `fetch_pull_request` once the cache is stale.
+    /// `request` must list the most recently updated pull requests first.
+    pub async fn fetch<P, F>(
+        &self,
+        request: reqwest::RequestBuilder,
+        fetch_pull_request: impl Fn(P) -> F,
+    ) -> Result<SyncReport, Error>
+    where
+        P: PullRequest,
+        F: Future<Output = Result<Vec<ChangeRequest>, Error>>,
+    {
+        self.scheduler.reset_progress();
+        let now = Utc::now();
+        let cached = Cache::load(self.store, &self.key, self.taxonomy).await?;
+        let (cache, since) = match Cache::plan(cached, self.sync_policy, now) {
+            Plan::Cached(cache) => return Ok(cache.into()),
+            Plan::Fetch { cache, since } => (cache, since),
+        };
+
+        // Neither API filters pull requests by update time, so stop at the
+        // first one already synced.
+        let pull_requests: Vec<P> = get_while(self.scheduler, request, |pull_request: &P| {
+            pull_request.updated_at() > since
+        })
+        .await?;
+        info!("Number of PR: {:?}", pull_requests.len());
+        self.scheduler.add_total(pull_requests.len());
+
+        let results = join_all(pull_requests.into_iter().map(|pull_request| {
+            let number = pull_request.number();
+            let change_requests = fetch_pull_request(pull_request);
+            async move {
+                let change_requests = change_requests.await;
+                self.scheduler.advance();
+                (self.name.to_string(), number, change_requests)
+            }
+        }))
+        .await;
+
+        Ok(cache.sync(self.store, &self.key, now, results).await)
+    }
+
+    /// Builds the change request of `note`, or `None` when it was not left by
+    /// one of the reviewers.
+    pub fn change_request(
+        &self,
+        pull_request: &impl PullRequest,
+        note: Note,
+    ) -> Option<ChangeRequest> {
+        let user = note
+            .user
+            .filter(|user| self.reviewers.matches(user, pull_request.user()))?;
+        let parsed_note = ParsedNote::from(note.body).normalize(self.taxonomy);
+        Some(ChangeRequest {
+            author: pull_request.user().username.clone(),
+            author_replied: false,
+            conventional_comment: parsed_note.conventional_comment,
+            created_at: note.created_at,
+            description: parsed_note.description,
+            id: note.id,
+            kind: note.kind,
+            merge_request: pull_request.metadata(),
+            merge_request_created_at: pull_request.created_at(),
+            merge_request_id: pull_request.number(),
+            merge_request_merged_at: pull_request.merged_at(),
+            original_tags: parsed_note.original_tags,
+            position: None,
+            project: self.name.to_string(),
+            reactions: vec![],
+            replies: 0,
+            // GitHub only exposes thread resolution through GraphQL, Gitea
+            // sets it on review comments.
+            resolvable: false,
+            resolved: false,
+            resolved_at: None,
+            resolved_by: None,
+            reviewer: user.username,
+            severity: parsed_note.severity,
+            suggestions: parsed_note.suggestions,
+            tags: parsed_note.tags,
+            updated_at: note.updated_at,
+            url: note.url,
+        })
+    }
+}
+
+/// GitHub and Gitea report merged pull requests as closed.
+pub(crate) fn merged(
+    state: MergeRequestState,
+    merged_at: Option<DateTime<Utc>>,
+) -> MergeRequestState {
+    match (state, merged_at) {
+        (MergeRequestState::Closed, Some(_)) => MergeRequestState::Merged,
+        (state, _) => state,
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Branch {
+    #[serde(rename = "ref")]
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Label {
+    pub name: String,
+}
@@ -0,0 +1,469 @@
+use chrono::{DateTime, Utc};
+use futures::future::try_join_all;
+use serde::Deserialize;
+
+use crate::{
+    change_request::{
+        ChangeRequest, DiffPosition, DiffStats, MergeRequestMetadata, MergeRequestState, NoteKind,
+    },
+    error::Error,
+    forge::{self, merged, Branch, Label, Note, Repository},
+    gitlab_client::Author,
+    pagination::get_all,
+    review_source::{ReviewSource, SyncReport},
+    reviewers::Reviewers,
+    scheduler::Scheduler,
+    store::Store,
+    sync_policy::SyncPolicy,
+    taxonomy::Taxonomy,
+};
+
+/// Reads pull request reviews and their comments from a Gitea or Forgejo
+/// instance. The `merge_request_id` of the resulting change requests is the
+/// PR index.
+#[derive(Clone)]
+pub struct GiteaClient<S> {
+    access_token: String,
+    base_url: String,
+    repository: String,
+    reviewers: Reviewers,
+    scheduler: Scheduler,
+    store: S,
+    sync_policy: SyncPolicy,
+    taxonomy: Option<Taxonomy>,
+}
+
+impl<S: Store> GiteaClient<S> {
+    /// `base_url` is the root of the instance, e.g. `https://codeberg.org`.
+    /// `repository` is `owner/name`.
+    pub fn new(
+        base_url: String,
+        access_token: String,
+        repository: String,
+        reviewers: Reviewers,
+        store: S,
+    ) -> Self {
+        let base_url = base_url.trim_end_matches('/').to_string();
+        Self {
+            access_token,
+            base_url,
+            repository,
+            reviewers,
+            scheduler: Scheduler::default(),
+            store,
+            sync_policy: SyncPolicy::default(),
+            taxonomy: None,
+        }
+    }
+
+    /// Sends every request through `scheduler` instead of the default one.
+    pub fn with_scheduler(mut self, scheduler: Scheduler) -> Self {
+        self.scheduler = scheduler;
+        self
+    }
+
+    pub fn with_sync_policy(mut self, sync_policy: SyncPolicy) -> Self {
+        self.sync_policy = sync_policy;
+        self
+    }
+
+    /// Normalizes the tags of every note to `taxonomy`.
+    pub fn with_taxonomy(mut self, taxonomy: Taxonomy) -> Self {
+        self.taxonomy = Some(taxonomy);
+        self
+    }
+
+    pub fn get(&self, endpoint: &str) -> reqwest::RequestBuilder {
+        let url = format!(
+            "{}/api/v1/repos/{}/{}",
+            self.base_url, self.repository, endpoint
+        );
+        let client = reqwest::Client::new();
+        client
+            .get(url)
+            .header("accept", "application/json")
+            .header("authorization", format!("token {}", self.access_token))
+            .query(&[("limit", "50")])
+    }
+
+    async fn fetch_pull_request(
+        &self,
+        pull_request: &PullRequest,
+    ) -> Result<Vec<ChangeRequest>, Error> {
+        let reviews: Vec<Review> = get_all(
+            &self.scheduler,
+            self.get(&format!("pulls/{}/reviews", pull_request.number)),
+        )
+        .await?;
+        let reviews = reviews
+            .into_iter()
+            .filter(|review| review.state != "PENDING")
+            .collect::<Vec<_>>();
+
+        let comments = try_join_all(
+            reviews
+                .iter()
+                .filter(|review| review.comments_count > 0)
+                .map(|review| {
+                    get_all::<ReviewComment>(
+                        &self.scheduler,
+                        self.get(&format!(
+                            "pulls/{}/reviews/{}/comments",
+                            pull_request.number, review.id
+                        )),
+                    )
+                }),
+        )
+        .await?;
+
+        let repository = self.repository();
+        let reviews = reviews.into_iter().filter_map(|review| {
+            repository.change_request(
+                pull_request,
+                Note {
+                    body: Some(review.body).filter(|body| !body.trim().is_empty())?,
+                    created_at: review.submitted_at?,
+                    id: review.id,
+                    kind: NoteKind::Review,
+                    updated_at: review.updated_at.or(review.submitted_at)?,
+                    url: review.html_url,
+                    user: review.user,
+                },
+            )
+        });
+
+        // Gitea has no thread ids: a conversation is every comment on the same
+        // line, and replies come back as comments of later reviews.
+        let mut comments = comments.into_iter().flatten().collect::<Vec<_>>();
+        comments.sort_by_key(|comment| comment.created_at);
+        let threads = comments
+            .iter()
+            .enumerate()
+            .filter(|(index, comment)| {
+                !comments[..*index]
+                    .iter()
+                    .any(|other| other.thread() == comment.thread())
+            })
+            .map(|(_, comment)| comment)
+            .collect::<Vec<_>>();
+        let comments = threads.into_iter().filter_map(|comment| {
+            let replies = comments
+                .iter()
+                .filter(|reply| reply.id != comment.id && reply.thread() == comment.thread())
+                .collect::<Vec<_>>();
+            Some(ChangeRequest {
+                author_replied: replies.iter().any(|reply| {
+                    reply
+                        .user
+                        .as_ref()
+                        .is_some_and(|user| user.id == pull_request.user.id)
+                }),
+                position: Some(comment.position()),
+                replies: replies.len(),
+                resolvable: true,
+                resolved: comment.resolver.is_some(),
+                resolved_by: comment.resolver.clone().map(|user| user.username),
+                ..repository.change_request(
+                    pull_request,
+                    Note {
+                        body: comment.body.clone(),
+                        created_at: comment.created_at,
+                        id: comment.id,
+                        kind: NoteKind::ReviewComment,
+                        updated_at: comment.updated_at,
+                        url: comment.html_url.clone(),
+                        user: comment.user.clone(),
+                    },
+                )?
+            })
+        });
+
+        Ok(reviews.chain(comments).collect())
+    }
+
+    fn repository(&self) -> Repository<'_, S> {
+        Repository {
+            key: format!("gitea/{}", self.repository),
+            name: &self.repository,
+            reviewers: &self.reviewers,
+            scheduler: &self.scheduler,
+            store: &self.store,
+            sync_policy: &self.sync_policy,
+            taxonomy: self.taxonomy.as_ref(),
+        }
+    }
+}
+
+impl<S: Store> ReviewSource for GiteaClient<S> {
+    /// Returns the cached change requests, syncing the pull requests updated
+    /// since the last sync once the cache is stale.
+    async fn fetch(&self) -> Result<SyncReport, Error> {
+        let request = self
+            .get("pulls")
+            .query(&[("state", "all"), ("sort", "recentupdate")]);
+        self.repository()
+            .fetch(request, |pull_request: PullRequest| async move {
+                self.fetch_pull_request(&pull_request).await
+            })
+            .await
+    }
+
+    async fn rebuild(&self) -> Result<SyncReport, Error> {
+        self.store.remove(&self.repository().key).await?;
+        self.fetch().await
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct PullRequest {
+    pub additions: Option<u64>,
+    pub base: Branch,
+    pub changed_files: Option<u64>,
+    pub created_at: DateTime<Utc>,
+    pub deletions: Option<u64>,
+    /// Only reported since Gitea 1.22.
+    #[serde(default)]
+    pub draft: bool,
+    pub labels: Vec<Label>,
+    pub merged_at: Option<DateTime<Utc>>,
+    pub number: u64,
+    pub state: MergeRequestState,
+    pub title: String,
+    pub updated_at: DateTime<Utc>,
+    pub user: Author,
+}
+
+impl forge::PullRequest for PullRequest {
+    fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    fn merged_at(&self) -> Option<DateTime<Utc>> {
+        self.merged_at
+    }
+
+    fn metadata(&self) -> MergeRequestMetadata {
+        let diff_stats = match (self.additions, self.deletions, self.changed_files) {
+            (Some(additions), Some(deletions), Some(files)) => Some(DiffStats {
+                additions,
+                deletions,
+                files,
+            }),
+            _ => None,
+        };
+        MergeRequestMetadata {
+            diff_stats,
+            draft: self.draft,
+            labels: self.labels.iter().map(|label| label.name.clone()).collect(),
+            state: merged(self.state, self.merged_at),
+            target_branch: self.base.name.clone(),
+            title: self.title.clone(),
+        }
+    }
+
+    fn number(&self) -> u64 {
+        self.number
+    }
+
+    fn updated_at(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+
+    fn user(&self) -> &Author {
+        &self.user
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Review {
+    pub body: String,
+    pub comments_count: u64,
+    pub html_url: String,
+    pub id: u64,
+    pub state: String,
+    pub submitted_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub user: Option<Author>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReviewComment {
+    pub body: String,
+    pub commit_id: String,
+    pub created_at: DateTime<Utc>,
+    pub html_url: String,
+    pub id: u64,
+    pub original_position: u64,
+    pub path: String,
+    pub position: u64,
+    pub resolver: Option<Author>,
+    pub updated_at: DateTime<Utc>,
+    pub user: Option<Author>,
+}
+
+impl ReviewComment {
+    fn thread(&self) -> (&str, u64, u64) {
+        (&self.path, self.position, self.original_position)
+    }
+
+    /// Gitea comments are on a single line: `position` on the new side of the
+    /// diff, or `original_position` on the old one, `0` standing for none.
+    fn position(&self) -> DiffPosition {
+        let line = Some(self.position)
+            .filter(|line| *line > 0)
+            .or(Some(self.original_position).filter(|line| *line > 0));
+        DiffPosition {
+            commit_sha: self.commit_id.clone(),
+            line_end: line,
+            line_start: line,
+            new_path: self.path.clone(),
+            old_path: self.path.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use wiremock::{
+        matchers::{header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::{reviewers::ReviewerId, store::MemoryStore};
+
+    fn user(id: u64) -> serde_json::Value {
+        json!({ "id": id, "login": format!("user-{}", id) })
+    }
+
+    #[tokio::test]
+    async fn test_fetch() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/repos/owner/repo/pulls"))
+            .and(header("authorization", "token token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+                { "additions": 12, "base": { "ref": "main" }, "changed_files": 2, "created_at": "2024-10-30T10:00:00Z", "deletions": 3, "labels": [{ "name": "kind/bug" }], "merged_at": null, "number": 3, "state": "open", "title": "Fix the parser", "updated_at": "2024-11-01T10:00:00Z", "user": user(1) },
+            ])))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/repos/owner/repo/pulls/3/reviews"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+                {
+                    "body": "Needs a test\n#testing/integration",
+                    "comments_count": 1,
+                    "html_url": "https://codeberg.org/owner/repo/pulls/3#issuecomment-10",
+                    "id": 10,
+                    "state": "REQUEST_CHANGES",
+                    "submitted_at": "2024-11-01T09:00:00Z",
+                    "updated_at": "2024-11-01T09:00:00Z",
+                    "user": user(2),
+                },
+                {
+                    "body": "Draft",
+                    "comments_count": 0,
+                    "html_url": "https://codeberg.org/owner/repo/pulls/3#issuecomment-11",
+                    "id": 11,
+                    "state": "PENDING",
+                    "submitted_at": null,
+                    "updated_at": null,
+                    "user": user(2),
+                },
+            ])))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/repos/owner/repo/pulls/3/reviews/10/comments"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+                {
+                    "body": "Typo\n#oversight/typo",
+                    "commit_id": "abc",
+                    "created_at": "2024-11-01T09:00:00Z",
+                    "html_url": "https://codeberg.org/owner/repo/pulls/3/files#issuecomment-10",
+                    "id": 10,
+                    "original_position": 0,
+                    "path": "src/main.rs",
+                    "position": 8,
+                    "resolver": user(1),
+                    "updated_at": "2024-11-01T09:00:00Z",
+                    "user": user(2),
+                },
+                {
+                    "body": "Fixed",
+                    "commit_id": "abc",
+                    "created_at": "2024-11-01T09:10:00Z",
+                    "html_url": "https://codeberg.org/owner/repo/pulls/3/files#issuecomment-11",
+                    "id": 11,
+                    "original_position": 0,
+                    "path": "src/main.rs",
+                    "position": 8,
+                    "resolver": null,
+                    "updated_at": "2024-11-01T09:10:00Z",
+                    "user": user(1),
+                },
+            ])))
+            .mount(&server)
+            .await;
+
+        let client = GiteaClient::new(
+            server.uri(),
+            "token".to_string(),
+            "owner/repo".to_string(),
+            Reviewers::Only(vec![ReviewerId::Username("user-2".to_string())]),
+            MemoryStore::new(),
+        );
+        let report = client.fetch().await.unwrap();
+        assert!(report.errors.is_empty());
+
+        let change_requests = report
+            .change_requests
+            .iter()
+            .map(|value| {
+                (
+                    value.key(),
+                    value.merge_request_id,
+                    value
+                        .tags
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert!(
+            change_requests
+                == vec![
+                    (
+                        (NoteKind::Review, 10),
+                        3,
+                        vec!["testing/integration".to_string()]
+                    ),
+                    (
+                        (NoteKind::ReviewComment, 10),
+                        3,
+                        vec!["oversight/typo".to_string()]
+                    ),
+                ]
+        );
+
+        let position = report.change_requests[1].position.as_ref().unwrap();
+        assert!(position.new_path == "src/main.rs" && position.line_start == Some(8));
+        let thread = &report.change_requests[1];
+        assert!(thread.replies == 1 && thread.author_replied);
+        assert!(thread.resolved && thread.resolved_by.as_deref() == Some("user-1"));
+
+        let merge_request = &report.change_requests[0].merge_request;
+        assert!(
+            merge_request.diff_stats
+                == Some(DiffStats {
+                    additions: 12,
+                    deletions: 3,
+                    files: 2,
+                })
+        );
+        assert!(merge_request.labels == vec!["kind/bug"] && !merge_request.draft);
+        assert!(merge_request.title == "Fix the parser");
+    }
+}
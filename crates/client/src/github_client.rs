@@ -0,0 +1,421 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::{
+    change_request::{
+        ChangeRequest, DiffPosition, MergeRequestMetadata, MergeRequestState, NoteKind,
+    },
+    error::Error,
+    forge::{self, merged, Branch, Label, Note, Repository},
+    gitlab_client::Author,
+    pagination::get_all,
+    review_source::{ReviewSource, SyncReport},
+    reviewers::Reviewers,
+    scheduler::Scheduler,
+    store::Store,
+    sync_policy::SyncPolicy,
+    taxonomy::Taxonomy,
+};
+
+/// Reads pull request review comments and review bodies from GitHub. The
+/// `merge_request_id` of the resulting change requests is the PR number.
+#[derive(Clone)]
+pub struct GithubClient<S> {
+    access_token: String,
+    base_url: String,
+    repository: String,
+    reviewers: Reviewers,
+    scheduler: Scheduler,
+    store: S,
+    sync_policy: SyncPolicy,
+    taxonomy: Option<Taxonomy>,
+}
+
+impl<S: Store> GithubClient<S> {
+    /// `base_url` is the API root, `https://api.github.com` or
+    /// `https://{host}/api/v3` for GitHub Enterprise. `repository` is `owner/name`.
+    pub fn new(
+        base_url: String,
+        access_token: String,
+        repository: String,
+        reviewers: Reviewers,
+        store: S,
+    ) -> Self {
+        let base_url = base_url.trim_end_matches('/').to_string();
+        Self {
+            access_token,
+            base_url,
+            repository,
+            reviewers,
+            scheduler: Scheduler::default(),
+            store,
+            sync_policy: SyncPolicy::default(),
+            taxonomy: None,
+        }
+    }
+
+    /// Sends every request through `scheduler` instead of the default one.
+    pub fn with_scheduler(mut self, scheduler: Scheduler) -> Self {
+        self.scheduler = scheduler;
+        self
+    }
+
+    pub fn with_sync_policy(mut self, sync_policy: SyncPolicy) -> Self {
+        self.sync_policy = sync_policy;
+        self
+    }
+
+    /// Normalizes the tags of every note to `taxonomy`.
+    pub fn with_taxonomy(mut self, taxonomy: Taxonomy) -> Self {
+        self.taxonomy = Some(taxonomy);
+        self
+    }
+
+    pub fn get(&self, endpoint: &str) -> reqwest::RequestBuilder {
+        let url = format!("{}/repos/{}/{}", self.base_url, self.repository, endpoint);
+        let client = reqwest::Client::new();
+        client
+            .get(url)
+            .header("accept", "application/vnd.github+json")
+            .header("authorization", format!("Bearer {}", self.access_token))
+            .header("user-agent", "reviewer")
+            .header("x-github-api-version", "2022-11-28")
+            .query(&[("per_page", "100")])
+    }
+
+    async fn fetch_pull_request(
+        &self,
+        pull_request: &PullRequest,
+    ) -> Result<Vec<ChangeRequest>, Error> {
+        let comments: Vec<ReviewComment> = get_all(
+            &self.scheduler,
+            self.get(&format!("pulls/{}/comments", pull_request.number)),
+        )
+        .await?;
+        let reviews: Vec<Review> = get_all(
+            &self.scheduler,
+            self.get(&format!("pulls/{}/reviews", pull_request.number)),
+        )
+        .await?;
+        let repository = self.repository();
+
+        // Replies are part of the thread started by the first comment.
+        let (comments, replies): (Vec<_>, Vec<_>) = comments
+            .into_iter()
+            .partition(|comment| comment.in_reply_to_id.is_none());
+        let comments = comments.into_iter().filter_map(|comment| {
+            let replies = replies
+                .iter()
+                .filter(|reply| reply.in_reply_to_id == Some(comment.id))
+                .collect::<Vec<_>>();
+            Some(ChangeRequest {
+                author_replied: replies.iter().any(|reply| {
+                    reply
+                        .user
+                        .as_ref()
+                        .is_some_and(|user| user.id == pull_request.user.id)
+                }),
+                position: Some(comment.position()),
+                replies: replies.len(),
+                ..repository.change_request(
+                    pull_request,
+                    Note {
+                        body: comment.body,
+                        created_at: comment.created_at,
+                        id: comment.id,
+                        kind: NoteKind::ReviewComment,
+                        updated_at: comment.updated_at,
+                        url: comment.html_url,
+                        user: comment.user,
+                    },
+                )?
+            })
+        });
+        let reviews = reviews.into_iter().filter_map(|review| {
+            repository.change_request(
+                pull_request,
+                Note {
+                    body: review.body.filter(|body| !body.trim().is_empty())?,
+                    created_at: review.submitted_at?,
+                    id: review.id,
+                    kind: NoteKind::Review,
+                    updated_at: review.submitted_at?,
+                    url: review.html_url,
+                    user: review.user,
+                },
+            )
+        });
+
+        Ok(comments.chain(reviews).collect())
+    }
+
+    fn repository(&self) -> Repository<'_, S> {
+        Repository {
+            key: format!("github/{}", self.repository),
+            name: &self.repository,
+            reviewers: &self.reviewers,
+            scheduler: &self.scheduler,
+            store: &self.store,
+            sync_policy: &self.sync_policy,
+            taxonomy: self.taxonomy.as_ref(),
+        }
+    }
+}
+
+impl<S: Store> ReviewSource for GithubClient<S> {
+    /// Returns the cached change requests, syncing the pull requests updated
+    /// since the last sync once the cache is stale.
+    async fn fetch(&self) -> Result<SyncReport, Error> {
+        let request = self.get("pulls").query(&[
+            ("state", "all"),
+            ("sort", "updated"),
+            ("direction", "desc"),
+        ]);
+        self.repository()
+            .fetch(request, |pull_request: PullRequest| async move {
+                self.fetch_pull_request(&pull_request).await
+            })
+            .await
+    }
+
+    async fn rebuild(&self) -> Result<SyncReport, Error> {
+        self.store.remove(&self.repository().key).await?;
+        self.fetch().await
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct PullRequest {
+    pub base: Branch,
+    pub created_at: DateTime<Utc>,
+    pub draft: bool,
+    pub labels: Vec<Label>,
+    pub merged_at: Option<DateTime<Utc>>,
+    pub number: u64,
+    pub state: MergeRequestState,
+    pub title: String,
+    pub updated_at: DateTime<Utc>,
+    pub user: Author,
+}
+
+impl forge::PullRequest for PullRequest {
+    fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    fn merged_at(&self) -> Option<DateTime<Utc>> {
+        self.merged_at
+    }
+
+    fn metadata(&self) -> MergeRequestMetadata {
+        MergeRequestMetadata {
+            // Only the endpoint of a single pull request reports its size.
+            diff_stats: None,
+            draft: self.draft,
+            labels: self.labels.iter().map(|label| label.name.clone()).collect(),
+            state: merged(self.state, self.merged_at),
+            target_branch: self.base.name.clone(),
+            title: self.title.clone(),
+        }
+    }
+
+    fn number(&self) -> u64 {
+        self.number
+    }
+
+    fn updated_at(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+
+    fn user(&self) -> &Author {
+        &self.user
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ReviewComment {
+    pub body: String,
+    pub commit_id: String,
+    pub created_at: DateTime<Utc>,
+    pub html_url: String,
+    pub id: u64,
+    pub in_reply_to_id: Option<u64>,
+    pub line: Option<u64>,
+    pub original_line: Option<u64>,
+    pub original_start_line: Option<u64>,
+    pub path: String,
+    pub start_line: Option<u64>,
+    pub updated_at: DateTime<Utc>,
+    pub user: Option<Author>,
+}
+
+impl ReviewComment {
+    /// Comments on outdated diffs only have their `original_*` lines left.
+    fn position(&self) -> DiffPosition {
+        let line_end = self.line.or(self.original_line);
+        DiffPosition {
+            commit_sha: self.commit_id.clone(),
+            line_end,
+            line_start: self.start_line.or(self.original_start_line).or(line_end),
+            new_path: self.path.clone(),
+            old_path: self.path.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Review {
+    pub body: Option<String>,
+    pub html_url: String,
+    pub id: u64,
+    pub submitted_at: Option<DateTime<Utc>>,
+    pub user: Option<Author>,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use wiremock::{
+        matchers::{header, method, path, query_param},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::store::MemoryStore;
+
+    fn user(id: u64) -> serde_json::Value {
+        json!({ "id": id, "login": format!("user-{}", id) })
+    }
+
+    #[tokio::test]
+    async fn test_fetch() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/repos/owner/repo/pulls"))
+            .and(header("authorization", "Bearer token"))
+            .and(query_param("page", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+            .expect(0)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/repos/owner/repo/pulls"))
+            .and(header("authorization", "Bearer token"))
+            .and(query_param("sort", "updated"))
+            .and(query_param("direction", "desc"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header(
+                        "link",
+                        format!(r#"<{}/repos/owner/repo/pulls?page=2>; rel="next""#, server.uri()),
+                    )
+                    .set_body_json(json!([
+                        { "base": { "ref": "main" }, "created_at": "2024-10-30T10:00:00Z", "draft": true, "labels": [{ "name": "bug" }], "merged_at": null, "number": 12, "state": "open", "title": "Fix the parser", "updated_at": "2024-11-01T10:00:00Z", "user": user(1) },
+                        { "base": { "ref": "main" }, "created_at": "2024-01-01T09:00:00Z", "draft": false, "labels": [], "merged_at": "2024-01-01T10:00:00Z", "number": 11, "state": "closed", "title": "Bump", "updated_at": "2024-01-01T10:00:00Z", "user": user(1) },
+                    ])),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/repos/owner/repo/pulls/12/comments"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+                {
+                    "body": "Rename this\n#naming/variable",
+                    "commit_id": "abc",
+                    "created_at": "2024-11-01T09:00:00Z",
+                    "html_url": "https://github.com/owner/repo/pull/12#discussion_r100",
+                    "id": 100,
+                    "in_reply_to_id": null,
+                    "line": 14,
+                    "original_line": 14,
+                    "original_start_line": null,
+                    "path": "src/lib.rs",
+                    "start_line": 12,
+                    "updated_at": "2024-11-01T09:00:00Z",
+                    "user": user(2),
+                },
+                {
+                    "body": "Done",
+                    "commit_id": "abc",
+                    "created_at": "2024-11-01T09:30:00Z",
+                    "html_url": "https://github.com/owner/repo/pull/12#discussion_r101",
+                    "id": 101,
+                    "in_reply_to_id": 100,
+                    "line": 14,
+                    "original_line": 14,
+                    "original_start_line": null,
+                    "path": "src/lib.rs",
+                    "start_line": 12,
+                    "updated_at": "2024-11-01T09:30:00Z",
+                    "user": user(1),
+                },
+            ])))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/repos/owner/repo/pulls/12/reviews"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+                {
+                    "body": "Missing tests\n#testing/unit",
+                    "html_url": "https://github.com/owner/repo/pull/12#pullrequestreview-200",
+                    "id": 200,
+                    "submitted_at": "2024-11-01T09:45:00Z",
+                    "user": user(2),
+                },
+                {
+                    "body": "",
+                    "html_url": "https://github.com/owner/repo/pull/12#pullrequestreview-201",
+                    "id": 201,
+                    "submitted_at": "2024-11-01T09:50:00Z",
+                    "user": user(3),
+                },
+            ])))
+            .mount(&server)
+            .await;
+
+        let client = GithubClient::new(
+            server.uri(),
+            "token".to_string(),
+            "owner/repo".to_string(),
+            Reviewers::NonAuthors,
+            MemoryStore::new(),
+        );
+        let report = client.fetch().await.unwrap();
+        assert!(report.errors.is_empty());
+
+        let change_requests = report
+            .change_requests
+            .iter()
+            .map(|value| {
+                (
+                    value.id,
+                    value.merge_request_id,
+                    value.reviewer.as_str(),
+                    value
+                        .tags
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert!(
+            change_requests
+                == vec![
+                    (100, 12, "user-2", vec!["naming/variable".to_string()]),
+                    (200, 12, "user-2", vec!["testing/unit".to_string()]),
+                ]
+        );
+
+        let position = report.change_requests[0].position.as_ref().unwrap();
+        assert!((position.line_start, position.line_end) == (Some(12), Some(14)));
+        assert!(report.change_requests[0].replies == 1 && report.change_requests[0].author_replied);
+        assert!(report.change_requests[1].position.is_none());
+
+        let merge_request = &report.change_requests[0].merge_request;
+        assert!(merge_request.draft && merge_request.diff_stats.is_none());
+        assert!(merge_request.labels == vec!["bug"]);
+        assert!(merge_request.state == MergeRequestState::Opened);
+        assert!(merge_request.target_branch == "main");
+    }
+}
@@ -1,149 +1,640 @@
-use chrono::{DateTime, TimeZone, Utc};
+use std::{collections::HashMap, convert::Infallible, str::FromStr};
+
+use chrono::{DateTime, Utc};
 use futures::future::join_all;
-use gloo_storage::{LocalStorage, Storage};
 use log::*;
 use serde::{Deserialize, Serialize};
 
-use crate::{change_request::ChangeRequest, parsed_note::ParsedNote};
+mod graphql;
+
+use crate::{
+    cache::{Cache, Plan},
+    change_request::{
+        ChangeRequest, DiffPosition, DiffStats, MergeRequestMetadata, MergeRequestState, NoteKind,
+        Reaction,
+    },
+    error::Error,
+    pagination::get_all,
+    parsed_note::ParsedNote,
+    review_source::{ReviewSource, SyncReport},
+    reviewers::Reviewers,
+    scheduler::Scheduler,
+    store::Store,
+    sync_policy::SyncPolicy,
+    taxonomy::Taxonomy,
+};
+
+/// How merge requests and their discussions are read from GitLab.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Transport {
+    /// One request per page of merge requests, plus one per merge request
+    /// for its discussions. Leaves out the size of merge requests, which
+    /// REST only reports one merge request at a time.
+    #[default]
+    Rest,
+    /// Merge requests and their discussions in batches. Projects must be
+    /// given by path and multiline comments only keep their last line.
+    GraphQl,
+}
+
+/// What happened when writing a change request back to its GitLab note.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SaveOutcome {
+    Saved {
+        updated_at: DateTime<Utc>,
+    },
+    /// The note already had the expected body, nothing was written.
+    Unchanged,
+    /// The note was edited on GitLab after it was fetched and was left alone.
+    Conflict {
+        updated_at: DateTime<Utc>,
+    },
+}
+
+/// Which merge requests are synced. Each project or group gets its own cache.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scope {
+    /// Projects by path (`group/project`) or id.
+    Projects(Vec<String>),
+    /// Every project of a group and its subgroups, by path or id.
+    Group(String),
+}
+
+/// Parses `group:<path>` as a group, or a comma-separated list of projects.
+impl FromStr for Scope {
+    type Err = Infallible;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        let str = str.trim();
+        if let Some(group) = str.strip_prefix("group:") {
+            return Ok(Scope::Group(group.trim().to_string()));
+        }
+
+        let projects = str
+            .split(',')
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        Ok(Scope::Projects(projects))
+    }
+}
 
-pub struct GitlabClient {
+#[derive(Clone)]
+pub struct GitlabClient<S> {
     access_token: String,
-    domain: String,
-    project: String,
+    base_url: String,
+    reviewers: Reviewers,
+    scheduler: Scheduler,
+    scope: Scope,
+    store: S,
+    sync_policies: HashMap<String, SyncPolicy>,
+    sync_policy: SyncPolicy,
+    taxonomy: Option<Taxonomy>,
+    transport: Transport,
 }
 
-impl GitlabClient {
-    pub fn new(access_token: String, project: String) -> Self {
-        let domain = "gitlab.com".to_string();
-        let project = urlencoding::encode(&project).to_string();
+impl<S: Store> GitlabClient<S> {
+    /// `base_url` is the root of the GitLab instance, e.g. `https://gitlab.com`
+    /// or `http://gitlab.internal:8080`.
+    pub fn new(
+        base_url: String,
+        access_token: String,
+        scope: Scope,
+        reviewers: Reviewers,
+        store: S,
+    ) -> Self {
+        let base_url = base_url.trim_end_matches('/').to_string();
         Self {
             access_token,
-            domain,
-            project,
+            base_url,
+            reviewers,
+            scheduler: Scheduler::default(),
+            scope,
+            store,
+            sync_policies: HashMap::new(),
+            sync_policy: SyncPolicy::default(),
+            taxonomy: None,
+            transport: Transport::default(),
         }
     }
 
+    /// Sends every request through `scheduler` instead of the default one.
+    pub fn with_scheduler(mut self, scheduler: Scheduler) -> Self {
+        self.scheduler = scheduler;
+        self
+    }
+
+    /// Reads through GraphQL rather than REST, see [`Transport`].
+    pub fn with_transport(mut self, transport: Transport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Normalizes the tags of every note to `taxonomy`.
+    pub fn with_taxonomy(mut self, taxonomy: Taxonomy) -> Self {
+        self.taxonomy = Some(taxonomy);
+        self
+    }
+
+    /// The policy of every project or group without one of its own.
+    pub fn with_sync_policy(mut self, sync_policy: SyncPolicy) -> Self {
+        self.sync_policy = sync_policy;
+        self
+    }
+
+    /// Overrides the policy of one project, or of the group, of the scope.
+    pub fn with_project_sync_policy(mut self, project: String, sync_policy: SyncPolicy) -> Self {
+        self.sync_policies.insert(project, sync_policy);
+        self
+    }
+
+    /// `endpoint` is relative to `/api/v4`, e.g. `projects/42/merge_requests`.
     pub fn get(&self, endpoint: &str) -> reqwest::RequestBuilder {
-        let url = format!(
-            "https://{}/api/v4/projects/{}/{}",
-            self.domain, self.project, endpoint
-        );
+        self.request(reqwest::Method::GET, endpoint)
+            .query(&[("per_page", "100")])
+    }
+
+    fn request(&self, method: reqwest::Method, endpoint: &str) -> reqwest::RequestBuilder {
+        let url = format!("{}/api/v4/{}", self.base_url, endpoint);
         let client = reqwest::Client::new();
         client
-            .get(url)
+            .request(method, url)
             .header("accept", "application/json")
             .header("private-token", self.access_token.clone())
-            .query(&[("per_page", "100")])
     }
 
-    pub async fn fetch(&self) -> Vec<ChangeRequest> {
-        let cache: Option<Cache> = LocalStorage::get("change_requests").ok();
+    /// Parses a note body, normalizing its tags against the taxonomy if any.
+    pub(crate) fn parse_note(&self, body: String) -> ParsedNote {
+        ParsedNote::from(body).normalize(self.taxonomy.as_ref())
+    }
+
+    /// Builds the change requests of a merge request from its discussions,
+    /// keeping only threads started by one of the reviewers. The rest of each
+    /// thread only counts towards its replies.
+    fn change_requests(
+        &self,
+        merge_request: &MergeRequest,
+        discussions: Vec<MergeRequestDiscussion>,
+    ) -> Vec<ChangeRequest> {
+        discussions
+            .into_iter()
+            .filter_map(|discussion| {
+                let mut notes = discussion.notes.into_iter();
+                let note = notes.next()?;
+                let replies = notes.filter(|reply| !reply.system).collect::<Vec<_>>();
+                Some((note, replies))
+            })
+            .filter(|(note, _)| {
+                !note.system && self.reviewers.matches(&note.author, &merge_request.author)
+            })
+            .map(|(note, replies)| {
+                let parsed_note = self.parse_note(note.body);
+                ChangeRequest {
+                    author: merge_request.author.username.clone(),
+                    author_replied: replies
+                        .iter()
+                        .any(|reply| reply.author.id == merge_request.author.id),
+                    conventional_comment: parsed_note.conventional_comment,
+                    created_at: note.created_at,
+                    description: parsed_note.description,
+                    id: note.id,
+                    kind: NoteKind::Note,
+                    merge_request: merge_request.metadata(),
+                    merge_request_created_at: merge_request.created_at,
+                    merge_request_id: merge_request.iid,
+                    merge_request_merged_at: merge_request.merged_at,
+                    original_tags: parsed_note.original_tags,
+                    position: note.position.map(DiffPosition::from),
+                    project: merge_request.project(),
+                    // Reactions only count towards tags, and REST has them one
+                    // request per note: both transports keep them for tagged
+                    // notes only.
+                    reactions: match parsed_note.tags.is_empty() {
+                        true => vec![],
+                        false => note.award_emoji.into_iter().map(Reaction::from).collect(),
+                    },
+                    replies: replies.len(),
+                    resolvable: note.resolvable,
+                    resolved: note.resolved,
+                    resolved_at: note.resolved_at,
+                    resolved_by: note.resolved_by.map(|author| author.username),
+                    reviewer: note.author.username,
+                    severity: parsed_note.severity,
+                    suggestions: parsed_note.suggestions,
+                    tags: parsed_note.tags,
+                    updated_at: note.updated_at,
+                    url: format!("{}/#note_{}", merge_request.web_url, note.id),
+                }
+            })
+            .collect()
+    }
+
+    /// Writes the tags of `change_request` back into its GitLab note,
+    /// unless the note was edited since it was fetched.
+    pub async fn save(&self, change_request: &ChangeRequest) -> Result<SaveOutcome, Error> {
+        let endpoint = format!(
+            "projects/{}/merge_requests/{}/notes/{}",
+            urlencoding::encode(&change_request.project),
+            change_request.merge_request_id,
+            change_request.id
+        );
+
+        let response = self
+            .scheduler
+            .send(self.request(reqwest::Method::GET, &endpoint))
+            .await?;
+        let note: MergeRequestNote = response.json().await?;
+        if note.updated_at != change_request.updated_at {
+            return Ok(SaveOutcome::Conflict {
+                updated_at: note.updated_at,
+            });
+        }
+
+        let body = ParsedNote::from(change_request).to_string();
+        if note.body == body {
+            return Ok(SaveOutcome::Unchanged);
+        }
 
-        if let Some(cache) = &cache {
-            if Utc::now() < cache.from + chrono::Duration::minutes(5) {
-                return cache.change_requests.clone();
+        let response = self
+            .scheduler
+            .send(
+                self.request(reqwest::Method::PUT, &endpoint)
+                    .json(&serde_json::json!({ "body": body })),
+            )
+            .await?;
+        let note: MergeRequestNote = response.json().await?;
+
+        // Keep the cache in step so that saving again does not see a conflict.
+        for key in self.cache_keys() {
+            let Some(mut cache) = Cache::load(&self.store, &key, self.taxonomy.as_ref()).await?
+            else {
+                continue;
+            };
+            let cached = cache
+                .change_requests
+                .iter_mut()
+                .find(|value| value.key() == change_request.key());
+            if let Some(cached) = cached {
+                *cached = ChangeRequest {
+                    updated_at: note.updated_at,
+                    ..change_request.clone()
+                };
+                cache.save(&self.store, &key).await?;
             }
         }
 
-        let from = match &cache {
-            Some(cache) => cache.from,
-            None => Utc.with_ymd_and_hms(2024, 10, 28, 12, 0, 0).unwrap(),
+        Ok(SaveOutcome::Saved {
+            updated_at: note.updated_at,
+        })
+    }
+}
+
+impl<S: Store> GitlabClient<S> {
+    /// The projects or group covered by the scope.
+    fn targets(&self) -> Vec<Target<'_>> {
+        let target = |path, group: bool| Target {
+            group,
+            key: match group {
+                true => format!("gitlab/group/{}", path),
+                false => format!("gitlab/project/{}", path),
+            },
+            path,
+            policy: self.sync_policies.get(path).unwrap_or(&self.sync_policy),
         };
+        match &self.scope {
+            Scope::Projects(projects) => projects
+                .iter()
+                .map(|project| target(project, false))
+                .collect(),
+            Scope::Group(group) => vec![target(group, true)],
+        }
+    }
 
-        let request = self
-            .get("merge_requests")
-            .query(&[("updated_after", from.to_string().as_str())]);
-        info!("request: {:?}", request);
-        let merge_requests = request
-            .send()
-            .await
-            .unwrap()
-            .json::<Vec<MergeRequest>>()
-            .await
-            .unwrap();
-        info!("Number of MR: {:?}", merge_requests.len());
+    fn cache_keys(&self) -> Vec<String> {
+        self.targets()
+            .into_iter()
+            .map(|target| target.key)
+            .collect()
+    }
 
-        let results = join_all(
-            merge_requests
+    /// The cache holding the change requests of a project, if it is in scope.
+    /// Groups given by id cannot be matched against a project path.
+    fn cache_key(&self, project: &str, project_id: u64) -> Option<String> {
+        match &self.scope {
+            Scope::Projects(projects) => projects
                 .iter()
-                .map(|merge_request| async {
-                    let discussions = self
-                        .get(&format!("merge_requests/{}/discussions", merge_request.iid))
-                        .send()
-                        .await
-                        .unwrap()
-                        .json::<Vec<MergeRequestDiscussion>>()
-                        .await
-                        .unwrap();
-                    (merge_request.clone(), discussions)
-                })
-                .collect::<Vec<_>>(),
-        )
-        .await;
+                .find(|value| *value == project || **value == project_id.to_string())
+                .map(|value| format!("gitlab/project/{}", value)),
+            Scope::Group(group) => project
+                .starts_with(&format!("{}/", group))
+                .then(|| format!("gitlab/group/{}", group)),
+        }
+    }
 
-        let mut change_requests = match cache {
-            Some(cache) => cache.change_requests,
-            None => vec![],
+    /// Syncs a single merge request into its cache, e.g. when a webhook
+    /// reports a change to it. Caches that were never synced are left alone,
+    /// the next fetch picks the merge request up along with the others.
+    pub async fn sync_merge_request(
+        &self,
+        project_id: u64,
+        iid: u64,
+    ) -> Result<Vec<ChangeRequest>, Error> {
+        let endpoint = format!("projects/{}/merge_requests/{}", project_id, iid);
+        let merge_request: MergeRequest = self
+            .scheduler
+            .send(self.get(&endpoint))
+            .await?
+            .json()
+            .await?;
+        let change_requests = self.fetch_change_requests(&merge_request).await?;
+
+        let project = merge_request.project();
+        let Some(key) = self.cache_key(&project, project_id) else {
+            return Ok(change_requests);
+        };
+        if let Some(mut cache) = Cache::load(&self.store, &key, self.taxonomy.as_ref()).await? {
+            cache.upsert(&project, iid, change_requests.clone());
+            cache.save(&self.store, &key).await?;
+        }
+        Ok(change_requests)
+    }
+
+    /// Applies `update` to a cached change request, returning it if the note
+    /// was found in the cache of its project.
+    #[cfg(feature = "webhook")]
+    pub(crate) async fn update_cached(
+        &self,
+        project: &str,
+        project_id: u64,
+        id: u64,
+        update: impl FnOnce(&mut ChangeRequest),
+    ) -> Result<Option<ChangeRequest>, Error> {
+        let Some(key) = self.cache_key(project, project_id) else {
+            return Ok(None);
+        };
+        let Some(mut cache) = Cache::load(&self.store, &key, self.taxonomy.as_ref()).await? else {
+            return Ok(None);
         };
+        let Some(change_request) = cache
+            .change_requests
+            .iter_mut()
+            .find(|value| value.id == id)
+        else {
+            return Ok(None);
+        };
+        update(change_request);
+        let change_request = change_request.clone();
+        cache.save(&self.store, &key).await?;
+        Ok(Some(change_request))
+    }
 
-        for (merge_request, discussions) in results {
-            for discussion in discussions {
-                if let Some(note) = discussion.notes.first() {
-                    if note.system == false && note.author.id == 20796726 {
-                        let parsed_note = ParsedNote::from(note.body.clone());
-                        let change_request = ChangeRequest {
-                            author: merge_request.author.username.clone(),
-                            category: parsed_note.category,
-                            description: parsed_note.description,
-                            id: note.id,
-                            merge_request_id: merge_request.iid,
-                            sub_category: parsed_note.sub_category,
-                            url: format!("{}/#note_{}", merge_request.web_url, note.id),
-                        };
-                        change_requests.push(change_request);
+    /// Merges change requests synced elsewhere, e.g. by a webhook receiver
+    /// running in another process, into the caches of their projects. Like
+    /// [`Self::sync_merge_request`], caches that were never synced are left
+    /// alone.
+    pub async fn cache_change_requests(
+        &self,
+        change_requests: &[ChangeRequest],
+    ) -> Result<(), Error> {
+        for key in self.cache_keys() {
+            let Some(mut cache) = Cache::load(&self.store, &key, self.taxonomy.as_ref()).await?
+            else {
+                continue;
+            };
+            let mut changed = false;
+            for change_request in change_requests {
+                let cached = cache.change_requests.iter_mut().find(|value| {
+                    value.project == change_request.project && value.key() == change_request.key()
+                });
+                match cached {
+                    Some(cached) => *cached = change_request.clone(),
+                    None if self.cache_key(&change_request.project, 0).as_ref() == Some(&key) => {
+                        cache.change_requests.push(change_request.clone())
                     }
+                    None => continue,
                 }
+                changed = true;
+            }
+            if changed {
+                cache.save(&self.store, &key).await?;
             }
         }
+        Ok(())
+    }
 
-        LocalStorage::set(
-            "change_requests",
-            Cache {
-                change_requests: change_requests.clone(),
-                from: Utc::now(),
-            },
+    /// Returns the cached change requests of one target, syncing the merge
+    /// requests updated since its last sync once the cache is stale.
+    async fn fetch_target(&self, target: &Target<'_>) -> Result<SyncReport, Error> {
+        let now = Utc::now();
+        let cached = Cache::load(&self.store, &target.key, self.taxonomy.as_ref()).await?;
+        let (cache, since) = match Cache::plan(cached, target.policy, now) {
+            Plan::Cached(cache) => return Ok(cache.into()),
+            Plan::Fetch { cache, since } => (cache, since),
+        };
+
+        let results = match self.transport {
+            Transport::Rest => self.fetch_rest(target, since).await?,
+            Transport::GraphQl => self.fetch_graphql(target, since).await?,
+        };
+
+        Ok(cache.sync(&self.store, &target.key, now, results).await)
+    }
+
+    /// Lists the merge requests, then fetches the discussions of each one.
+    async fn fetch_rest(
+        &self,
+        target: &Target<'_>,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<SyncResult>, Error> {
+        let merge_requests: Vec<MergeRequest> = get_all(
+            &self.scheduler,
+            self.get(&target.endpoint())
+                .query(&[("updated_after", since.to_rfc3339().as_str())]),
         )
-        .ok();
+        .await?;
+        info!("Number of MR in {}: {:?}", target.key, merge_requests.len());
+        self.scheduler.add_total(merge_requests.len());
+
+        let results = join_all(merge_requests.iter().map(|merge_request| async {
+            let change_requests = self.fetch_change_requests(merge_request).await;
+            self.scheduler.advance();
+            (merge_request.project(), merge_request.iid, change_requests)
+        }))
+        .await;
+
+        Ok(results)
+    }
+
+    /// Fetches the discussions of a merge request, which listing merge
+    /// requests leaves out, and builds its change requests.
+    async fn fetch_change_requests(
+        &self,
+        merge_request: &MergeRequest,
+    ) -> Result<Vec<ChangeRequest>, Error> {
+        let discussions = self.fetch_discussions(merge_request).await?;
+        let mut change_requests = self.change_requests(merge_request, discussions);
+        self.fetch_reactions(merge_request, &mut change_requests)
+            .await;
+        Ok(change_requests)
+    }
 
-        change_requests
+    /// Fetches the award emoji of the tagged change requests, see
+    /// [`GitlabClient::change_requests`]. Reactions are best effort: a note
+    /// whose award emoji fail to load is kept without any.
+    async fn fetch_reactions(
+        &self,
+        merge_request: &MergeRequest,
+        change_requests: &mut [ChangeRequest],
+    ) {
+        let tagged = change_requests
+            .iter_mut()
+            .filter(|change_request| !change_request.tags.is_empty());
+        join_all(tagged.map(|change_request| async {
+            let award_emoji: Result<Vec<AwardEmoji>, Error> = get_all(
+                &self.scheduler,
+                self.get(&format!(
+                    "projects/{}/merge_requests/{}/notes/{}/award_emoji",
+                    merge_request.project_id, merge_request.iid, change_request.id
+                )),
+            )
+            .await;
+            change_request.reactions = match award_emoji {
+                Ok(award_emoji) => award_emoji.into_iter().map(Reaction::from).collect(),
+                Err(error) => {
+                    warn!(
+                        "Failed to fetch the reactions of {}: {}",
+                        change_request.url, error
+                    );
+                    vec![]
+                }
+            };
+        }))
+        .await;
     }
 
-    // pub async fn save(&self, change_request: &ChangeRequest) {
-    //     let parsed_note = ParsedNote::from(change_request);
-    //     let endpoint = EditMergeRequestNote::builder()
-    //         .project("archipels-managed/connect-monorepo")
-    //         .merge_request(change_request.merge_request_id)
-    //         .note(change_request.id)
-    //         .body(parsed_note.to_string())
-    //         .build()
-    //         .unwrap();
-    //     api::ignore(endpoint).query_async(&self.0).await.unwrap();
-    // }
+    async fn fetch_discussions(
+        &self,
+        merge_request: &MergeRequest,
+    ) -> Result<Vec<MergeRequestDiscussion>, Error> {
+        get_all(
+            &self.scheduler,
+            self.get(&format!(
+                "projects/{}/merge_requests/{}/discussions",
+                merge_request.project_id, merge_request.iid
+            )),
+        )
+        .await
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct Cache {
-    pub change_requests: Vec<ChangeRequest>,
-    pub from: DateTime<Utc>,
+/// The change requests of one merge request, or why they could not be synced.
+type SyncResult = (String, u64, Result<Vec<ChangeRequest>, Error>);
+
+/// A project or group of the scope, synced into a cache of its own.
+struct Target<'a> {
+    group: bool,
+    key: String,
+    path: &'a str,
+    policy: &'a SyncPolicy,
+}
+
+impl Target<'_> {
+    fn endpoint(&self) -> String {
+        let kind = if self.group { "groups" } else { "projects" };
+        format!("{}/{}/merge_requests", kind, urlencoding::encode(self.path))
+    }
+}
+
+impl<S: Store> ReviewSource for GitlabClient<S> {
+    /// Fetches every project of the scope. Merge requests whose discussions
+    /// fail to load are reported in [`SyncReport::errors`].
+    /// Fails only when no project or group could be synced at all, the
+    /// errors of the others are reported along with what was fetched.
+    async fn fetch(&self) -> Result<SyncReport, Error> {
+        self.scheduler.reset_progress();
+        let targets = self.targets();
+        let results = join_all(targets.iter().map(|target| self.fetch_target(target))).await;
+
+        let mut report = SyncReport {
+            change_requests: vec![],
+            errors: vec![],
+        };
+        let mut synced = false;
+        for (target, result) in targets.iter().zip(results) {
+            match result {
+                Ok(result) => {
+                    synced = true;
+                    report.change_requests.extend(result.change_requests);
+                    report.errors.extend(result.errors);
+                }
+                Err(error) => {
+                    warn!("Failed to sync {}: {}", target.key, error);
+                    report.errors.push(error);
+                }
+            }
+        }
+        match synced || report.errors.is_empty() {
+            true => Ok(report),
+            false => Err(report.errors.swap_remove(0)),
+        }
+    }
+
+    async fn rebuild(&self) -> Result<SyncReport, Error> {
+        for key in self.cache_keys() {
+            self.store.remove(&key).await?;
+        }
+        self.fetch().await
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
 struct MergeRequest {
     pub author: Author,
+    pub created_at: DateTime<Utc>,
+    /// Only reported by GraphQL, see [`Transport::Rest`].
+    #[serde(skip)]
+    pub diff_stats: Option<DiffStats>,
+    pub draft: bool,
     pub iid: u64,
+    pub labels: Vec<String>,
+    pub merged_at: Option<DateTime<Utc>>,
+    pub project_id: u64,
+    pub references: References,
+    pub state: MergeRequestState,
+    pub target_branch: String,
+    pub title: String,
     pub web_url: String,
 }
 
+impl MergeRequest {
+    /// The path of the project, e.g. `group/project`.
+    fn project(&self) -> String {
+        let reference = &self.references.full;
+        match reference.rsplit_once('!') {
+            Some((project, _)) => project.to_string(),
+            None => reference.clone(),
+        }
+    }
+
+    fn metadata(&self) -> MergeRequestMetadata {
+        MergeRequestMetadata {
+            diff_stats: self.diff_stats,
+            draft: self.draft,
+            labels: self.labels.clone(),
+            state: self.state,
+            target_branch: self.target_branch.clone(),
+            title: self.title.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct References {
+    pub full: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct MergeRequestDiscussion {
     pub notes: Vec<MergeRequestNote>,
@@ -151,14 +642,583 @@ pub struct MergeRequestDiscussion {
 
 #[derive(Debug, Deserialize)]
 pub struct MergeRequestNote {
+    /// Left empty by REST, see [`GitlabClient::fetch_reactions`].
+    #[serde(default)]
+    pub award_emoji: Vec<AwardEmoji>,
     pub author: Author,
     pub body: String,
+    pub created_at: DateTime<Utc>,
     pub id: u64,
+    pub position: Option<NotePosition>,
+    #[serde(default)]
+    pub resolvable: bool,
+    #[serde(default)]
+    pub resolved: bool,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub resolved_by: Option<Author>,
     pub system: bool,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AwardEmoji {
+    pub name: String,
+    pub user: Author,
+}
+
+impl From<AwardEmoji> for Reaction {
+    fn from(emoji: AwardEmoji) -> Self {
+        Reaction {
+            name: emoji.name,
+            user: emoji.user.username,
+        }
+    }
+}
+
+/// The `position` of a `DiffNote`. Multiline comments carry a `line_range`,
+/// single line ones only `old_line` for removed lines or `new_line` otherwise.
+#[derive(Debug, Deserialize)]
+pub struct NotePosition {
+    pub head_sha: String,
+    pub line_range: Option<LineRange>,
+    pub new_line: Option<u64>,
+    pub new_path: String,
+    pub old_line: Option<u64>,
+    pub old_path: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LineRange {
+    pub end: Line,
+    pub start: Line,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Line {
+    pub new_line: Option<u64>,
+    pub old_line: Option<u64>,
+}
+
+impl From<NotePosition> for DiffPosition {
+    fn from(position: NotePosition) -> Self {
+        let line = position.new_line.or(position.old_line);
+        let (line_start, line_end) = match position.line_range {
+            Some(range) => (
+                range.start.new_line.or(range.start.old_line),
+                range.end.new_line.or(range.end.old_line),
+            ),
+            None => (line, line),
+        };
+        DiffPosition {
+            commit_sha: position.head_sha,
+            line_end,
+            line_start,
+            new_path: position.new_path,
+            old_path: position.old_path,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Author {
     pub id: u64,
+    #[serde(alias = "login")]
     pub username: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use wiremock::{
+        matchers::{body_json, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::{
+        change_request::Tag,
+        reviewers::ReviewerId,
+        store::MemoryStore,
+        test_support::{change_request, mount, note},
+    };
+
+    /// Pretends the last sync is old enough for the next fetch to hit the server.
+    async fn expire(store: &MemoryStore) {
+        let key = "gitlab/project/group/project";
+        let mut cache: serde_json::Value =
+            serde_json::from_str(&store.load(key).await.unwrap().unwrap()).unwrap();
+        cache["from"] = json!("2024-10-28T12:00:00Z");
+        store.save(key, cache.to_string()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_fetch_is_idempotent() {
+        let server = MockServer::start().await;
+        let store = MemoryStore::new();
+        let client = GitlabClient::new(
+            server.uri(),
+            "token".to_string(),
+            Scope::Projects(vec!["group/project".to_string()]),
+            Reviewers::Only(vec![ReviewerId::Id(2)]),
+            store.clone(),
+        );
+
+        mount(
+            &server,
+            json!([
+                { "notes": [note(100, 2, "first"), note(101, 1, "reply")] },
+                { "notes": [note(200, 2, "second\n#testing/unit")] },
+                { "notes": [note(300, 3, "not a reviewer")] },
+            ]),
+        )
+        .await;
+
+        let first = client.fetch().await.unwrap().change_requests;
+        assert!(first.iter().map(|value| value.id).collect::<Vec<_>>() == vec![100, 200]);
+
+        for _ in 0..2 {
+            expire(&store).await;
+            let again = client.fetch().await.unwrap().change_requests;
+            assert!(again == first);
+        }
+
+        mount(
+            &server,
+            json!([
+                { "notes": [note(200, 2, "second, edited\n#testing/unit")] },
+                { "notes": [note(400, 2, "third")] },
+            ]),
+        )
+        .await;
+        expire(&store).await;
+
+        let change_requests = client.fetch().await.unwrap().change_requests;
+        assert!(
+            change_requests
+                .iter()
+                .map(|value| (value.id, value.description.as_str()))
+                .collect::<Vec<_>>()
+                == vec![(200, "second, edited"), (400, "third")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sync_policy() {
+        let server = MockServer::start().await;
+        mount(&server, json!([{ "notes": [note(100, 2, "first")] }])).await;
+        let client = GitlabClient::new(
+            server.uri(),
+            "token".to_string(),
+            Scope::Projects(vec!["group/project".to_string()]),
+            Reviewers::NonAuthors,
+            MemoryStore::new(),
+        )
+        .with_project_sync_policy(
+            "group/project".to_string(),
+            SyncPolicy {
+                start: "2024-01-01T00:00:00Z".parse().unwrap(),
+                ..SyncPolicy::default()
+            },
+        );
+        let updated_after = || async {
+            server
+                .received_requests()
+                .await
+                .unwrap()
+                .iter()
+                .filter(|request| request.url.path().ends_with("/merge_requests"))
+                .filter_map(|request| {
+                    request
+                        .url
+                        .query_pairs()
+                        .find(|(key, _)| key == "updated_after")
+                        .map(|(_, value)| value.to_string())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        client.fetch().await.unwrap();
+        client.fetch().await.unwrap();
+        assert!(updated_after().await == vec!["2024-01-01T00:00:00+00:00"]);
+
+        let report = client.rebuild().await.unwrap();
+        assert!(report.change_requests.len() == 1);
+        assert!(updated_after().await.len() == 2);
+    }
+
+    #[tokio::test]
+    async fn test_save() {
+        let server = MockServer::start().await;
+        let client = GitlabClient::new(
+            server.uri(),
+            "token".to_string(),
+            Scope::Projects(vec!["group/project".to_string()]),
+            Reviewers::NonAuthors,
+            MemoryStore::new(),
+        );
+        let endpoint = "/api/v4/projects/group%2Fproject/merge_requests/7/notes/100";
+        let fetched_at = "2024-11-01T10:00:00Z".parse().unwrap();
+
+        let mut change_request = change_request(100, 7, "first");
+        change_request.updated_at = fetched_at;
+        change_request.tags = vec![Tag::from("testing/unit")];
+
+        Mock::given(method("GET"))
+            .and(path(endpoint))
+            .respond_with(ResponseTemplate::new(200).set_body_json(note(100, 2, "first")))
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path(endpoint))
+            .and(body_json(json!({ "body": "first  \n#testing/unit" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json({
+                let mut note = note(100, 2, "first  \n#testing/unit");
+                note["updated_at"] = json!("2024-11-02T10:00:00Z");
+                note
+            }))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let outcome = client.save(&change_request).await.unwrap();
+        assert!(
+            outcome
+                == SaveOutcome::Saved {
+                    updated_at: "2024-11-02T10:00:00Z".parse().unwrap()
+                }
+        );
+
+        server.reset().await;
+        Mock::given(method("GET"))
+            .and(path(endpoint))
+            .respond_with(ResponseTemplate::new(200).set_body_json({
+                let mut note = note(100, 2, "first, edited on GitLab");
+                note["updated_at"] = json!("2024-11-03T10:00:00Z");
+                note
+            }))
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let outcome = client.save(&change_request).await.unwrap();
+        assert!(
+            outcome
+                == SaveOutcome::Conflict {
+                    updated_at: "2024-11-03T10:00:00Z".parse().unwrap()
+                }
+        );
+
+        server.reset().await;
+        Mock::given(method("GET"))
+            .and(path(endpoint))
+            .respond_with(ResponseTemplate::new(200).set_body_json(note(
+                100,
+                2,
+                "first  \n#testing/unit",
+            )))
+            .mount(&server)
+            .await;
+
+        let outcome = client.save(&change_request).await.unwrap();
+        assert!(outcome == SaveOutcome::Unchanged);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_keeps_other_targets() {
+        let server = MockServer::start().await;
+        mount(&server, json!([{ "notes": [note(100, 2, "first")] }])).await;
+        let client = GitlabClient::new(
+            server.uri(),
+            "token".to_string(),
+            Scope::Projects(vec!["group/project".to_string(), "group/gone".to_string()]),
+            Reviewers::NonAuthors,
+            MemoryStore::new(),
+        );
+
+        let report = client.fetch().await.unwrap();
+        assert!(report.change_requests.len() == 1);
+        assert!(
+            matches!(&report.errors[..], [Error::Network(message)] if message.contains("gone"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_reactions() {
+        let server = MockServer::start().await;
+        mount(
+            &server,
+            json!([
+                { "notes": [note(100, 2, "first\n#naming/variable")] },
+                { "notes": [note(200, 2, "second\n#naming/variable")] },
+            ]),
+        )
+        .await;
+        let award_emoji = |id| {
+            format!(
+                "/api/v4/projects/42/merge_requests/7/notes/{}/award_emoji",
+                id
+            )
+        };
+        Mock::given(method("GET"))
+            .and(path(award_emoji(100)))
+            .respond_with(ResponseTemplate::new(404))
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(award_emoji(200)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+                { "name": "thumbsup", "user": { "id": 3, "username": "user-3" } },
+            ])))
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        let client = GitlabClient::new(
+            server.uri(),
+            "token".to_string(),
+            Scope::Projects(vec!["group/project".to_string()]),
+            Reviewers::NonAuthors,
+            MemoryStore::new(),
+        );
+
+        // The reactions of note 100 failing to load does not drop the merge
+        // request.
+        let report = client.fetch().await.unwrap();
+        assert!(report.errors.is_empty());
+        let reactions = report
+            .change_requests
+            .iter()
+            .map(|value| (value.id, value.reactions.len()))
+            .collect::<Vec<_>>();
+        assert!(reactions == vec![(100, 0), (200, 1)]);
+    }
+
+    #[tokio::test]
+    async fn test_cache_change_requests() {
+        let server = MockServer::start().await;
+        let client = GitlabClient::new(
+            server.uri(),
+            "token".to_string(),
+            Scope::Projects(vec!["group/project".to_string()]),
+            Reviewers::Only(vec![ReviewerId::Id(2)]),
+            MemoryStore::new(),
+        );
+        let pushed = vec![
+            change_request(100, 7, "edited"),
+            change_request(300, 7, "new"),
+            ChangeRequest {
+                project: "group/other".to_string(),
+                ..change_request(400, 7, "out of scope")
+            },
+        ];
+
+        client.cache_change_requests(&pushed).await.unwrap();
+        assert!(client
+            .store
+            .load("gitlab/project/group/project")
+            .await
+            .unwrap()
+            .is_none());
+
+        mount(
+            &server,
+            json!([
+                { "notes": [note(100, 2, "first")] },
+                { "notes": [note(200, 2, "second")] },
+            ]),
+        )
+        .await;
+        client.fetch().await.unwrap();
+        client.cache_change_requests(&pushed).await.unwrap();
+
+        server.reset().await;
+        let report = client.fetch().await.unwrap();
+        assert!(
+            report
+                .change_requests
+                .iter()
+                .map(|value| (value.id, value.description.as_str()))
+                .collect::<Vec<_>>()
+                == vec![(100, "edited"), (200, "second"), (300, "new")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_threads() {
+        let server = MockServer::start().await;
+        let mut resolved = note(100, 2, "first");
+        resolved["resolvable"] = json!(true);
+        resolved["resolved"] = json!(true);
+        resolved["resolved_at"] = json!("2024-11-02T10:00:00Z");
+        resolved["resolved_by"] = json!({ "id": 1, "username": "user-1" });
+        let mut system = note(103, 1, "resolved all threads");
+        system["system"] = json!(true);
+        mount(
+            &server,
+            json!([
+                { "notes": [resolved, note(101, 1, "done"), note(102, 2, "thanks"), system] },
+                { "notes": [note(200, 2, "second"), note(201, 3, "+1")] },
+            ]),
+        )
+        .await;
+
+        let client = GitlabClient::new(
+            server.uri(),
+            "token".to_string(),
+            Scope::Projects(vec!["group/project".to_string()]),
+            Reviewers::Only(vec![ReviewerId::Id(2)]),
+            MemoryStore::new(),
+        );
+        let change_requests = client.fetch().await.unwrap().change_requests;
+        assert!(
+            change_requests
+                .iter()
+                .map(|value| (
+                    value.id,
+                    value.replies,
+                    value.author_replied,
+                    value.resolved,
+                    value.resolved_by.as_deref(),
+                ))
+                .collect::<Vec<_>>()
+                == vec![
+                    (100, 2, true, true, Some("user-1")),
+                    (200, 1, false, false, None),
+                ]
+        );
+        assert!(change_requests[0].resolved_at == Some("2024-11-02T10:00:00Z".parse().unwrap()));
+
+        let merge_request = &change_requests[0].merge_request;
+        assert!(merge_request.title == "Fix the parser" && merge_request.labels == vec!["bug"]);
+        assert!(merge_request.state == MergeRequestState::Opened);
+        assert!(merge_request.diff_stats.is_none());
+    }
+
+    #[test]
+    fn test_diff_position_from_note_position() {
+        let position = |value: serde_json::Value| {
+            DiffPosition::from(serde_json::from_value::<NotePosition>(value).unwrap())
+        };
+
+        let single = position(json!({
+            "base_sha": "aaa",
+            "head_sha": "ccc",
+            "line_range": null,
+            "new_line": null,
+            "new_path": "src/lib.rs",
+            "old_line": 12,
+            "old_path": "src/lib.rs",
+            "position_type": "text",
+            "start_sha": "bbb",
+        }));
+        assert!(
+            single
+                == DiffPosition {
+                    commit_sha: "ccc".to_string(),
+                    line_end: Some(12),
+                    line_start: Some(12),
+                    new_path: "src/lib.rs".to_string(),
+                    old_path: "src/lib.rs".to_string(),
+                }
+        );
+
+        let multiline = position(json!({
+            "head_sha": "ccc",
+            "line_range": {
+                "end": { "line_code": "x_20_22", "new_line": 22, "old_line": 20, "type": null },
+                "start": { "line_code": "x_18_20", "new_line": null, "old_line": 18, "type": "old" },
+            },
+            "new_line": 22,
+            "new_path": "src/new.rs",
+            "old_line": 20,
+            "old_path": "src/old.rs",
+        }));
+        assert!((multiline.line_start, multiline.line_end) == (Some(18), Some(22)));
+        assert!(multiline.old_path == "src/old.rs");
+    }
+
+    #[test]
+    fn test_scope_from_str() {
+        let scope: Scope = "group/a, group/b".parse().unwrap();
+        assert!(scope == Scope::Projects(vec!["group/a".to_string(), "group/b".to_string()]));
+
+        let scope: Scope = "group:group/subgroup".parse().unwrap();
+        assert!(scope == Scope::Group("group/subgroup".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_group() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v4/groups/group/merge_requests"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+                {
+                    "author": { "id": 1, "username": "user-1" },
+                    "created_at": "2024-10-30T10:00:00Z",
+                    "draft": false,
+                    "iid": 7,
+                    "labels": ["bug"],
+                    "merged_at": null,
+                    "project_id": 42,
+                    "references": { "full": "group/a!7" },
+                    "state": "opened",
+                    "target_branch": "main",
+                    "title": "Fix the parser",
+                    "web_url": "https://gitlab.com/group/a/-/merge_requests/7",
+                },
+                {
+                    "author": { "id": 1, "username": "user-1" },
+                    "created_at": "2024-10-30T10:00:00Z",
+                    "draft": false,
+                    "iid": 7,
+                    "labels": ["bug"],
+                    "merged_at": null,
+                    "project_id": 43,
+                    "references": { "full": "group/b!7" },
+                    "state": "opened",
+                    "target_branch": "main",
+                    "title": "Fix the parser",
+                    "web_url": "https://gitlab.com/group/b/-/merge_requests/7",
+                },
+            ])))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v4/projects/42/merge_requests/7/discussions"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!([{ "notes": [note(100, 2, "a")] }])),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v4/projects/43/merge_requests/7/discussions"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!([{ "notes": [note(200, 2, "b")] }])),
+            )
+            .mount(&server)
+            .await;
+
+        let store = MemoryStore::new();
+        let client = GitlabClient::new(
+            server.uri(),
+            "token".to_string(),
+            Scope::Group("group".to_string()),
+            Reviewers::NonAuthors,
+            store.clone(),
+        );
+
+        let change_requests = client.fetch().await.unwrap().change_requests;
+        assert!(
+            change_requests
+                .iter()
+                .map(|value| (value.project.as_str(), value.merge_request_id, value.id))
+                .collect::<Vec<_>>()
+                == vec![("group/a", 7, 100), ("group/b", 7, 200)]
+        );
+        assert!(store.load("gitlab/group/group").await.unwrap().is_some());
+    }
+}
@@ -0,0 +1,729 @@
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use log::*;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
+
+use super::{
+    Author, AwardEmoji, DiffStats, GitlabClient, MergeRequest, MergeRequestDiscussion,
+    MergeRequestNote, MergeRequestState, NotePosition, References, SyncResult, Target,
+};
+use crate::{change_request::ChangeRequest, error::Error, store::Store};
+
+/// GitLab caps the complexity of a query, which grows with the number of
+/// merge requests times the number of discussions asked for at once.
+const MERGE_REQUESTS_PER_PAGE: usize = 20;
+const DISCUSSIONS_PER_PAGE: usize = 100;
+
+const MERGE_REQUEST_FIELDS: &str = "
+      pageInfo { hasNextPage endCursor }
+      nodes {
+        author { id username }
+        createdAt
+        diffStatsSummary { additions deletions fileCount }
+        draft
+        iid
+        labels { nodes { title } }
+        mergedAt
+        projectId
+        reference(full: true)
+        state
+        targetBranch
+        title
+        webUrl
+        discussions(first: $discussions) {
+          pageInfo { hasNextPage endCursor }
+          nodes {
+            notes {
+              nodes {
+                author { id username }
+                awardEmoji { nodes { name user { id username } } }
+                body
+                createdAt
+                id
+                position {
+                  diffRefs { headSha }
+                  newLine
+                  newPath
+                  oldLine
+                  oldPath
+                }
+                resolvable
+                resolved
+                resolvedAt
+                resolvedBy { id username }
+                system
+                updatedAt
+              }
+            }
+          }
+        }
+      }";
+
+impl<S: Store> GitlabClient<S> {
+    /// Lists the merge requests of `target` along with their discussions,
+    /// a page at a time. Merge requests with more discussions than fit in
+    /// the page get the rest of them through REST.
+    pub(super) async fn fetch_graphql(
+        &self,
+        target: &Target<'_>,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<SyncResult>, Error> {
+        let query = query(target.group);
+        let mut after = None;
+        let mut results = vec![];
+
+        loop {
+            let data: Data = self
+                .graphql(
+                    &query,
+                    json!({
+                        "after": after,
+                        "discussions": DISCUSSIONS_PER_PAGE,
+                        "fullPath": target.path,
+                        "updatedAfter": since.to_rfc3339(),
+                    }),
+                )
+                .await?;
+            let merge_requests = data
+                .project
+                .or(data.group)
+                .ok_or_else(|| Error::Network(format!("{} was not found", target.path)))?
+                .merge_requests;
+            self.scheduler.add_total(merge_requests.nodes.len());
+
+            let page = merge_requests
+                .nodes
+                .into_iter()
+                .map(MergeRequestNode::split)
+                .collect::<Result<Vec<_>, Error>>()?;
+            results.extend(
+                join_all(
+                    page.into_iter()
+                        .map(|(merge_request, discussions)| async move {
+                            let change_requests = match discussions {
+                                Some(discussions) => {
+                                    Ok(self.change_requests(&merge_request, discussions))
+                                }
+                                None => self.fetch_remaining(&merge_request).await,
+                            };
+                            self.scheduler.advance();
+                            (merge_request.project(), merge_request.iid, change_requests)
+                        }),
+                )
+                .await,
+            );
+
+            match merge_requests.page_info.end_cursor {
+                Some(cursor) if merge_requests.page_info.has_next_page => after = Some(cursor),
+                _ => break,
+            }
+        }
+
+        info!("Number of MR in {}: {:?}", target.key, results.len());
+        Ok(results)
+    }
+
+    /// Builds the change requests of a merge request with too many
+    /// discussions for a page from REST instead.
+    async fn fetch_remaining(
+        &self,
+        merge_request: &MergeRequest,
+    ) -> Result<Vec<ChangeRequest>, Error> {
+        let discussions = self.fetch_discussions(merge_request).await?;
+        let mut change_requests = self.change_requests(merge_request, discussions);
+        self.fetch_reactions(merge_request, &mut change_requests)
+            .await;
+        Ok(change_requests)
+    }
+
+    async fn graphql<T: DeserializeOwned>(
+        &self,
+        query: &str,
+        variables: serde_json::Value,
+    ) -> Result<T, Error> {
+        let request = reqwest::Client::new()
+            .post(format!("{}/api/graphql", self.base_url))
+            .bearer_auth(&self.access_token)
+            .json(&json!({ "query": query, "variables": variables }));
+        let response: Response<T> = self.scheduler.send(request).await?.json().await?;
+
+        // GitLab answers 200 with an `errors` list when the query itself failed.
+        if !response.errors.is_empty() {
+            let messages = response
+                .errors
+                .into_iter()
+                .map(|error| error.message)
+                .collect::<Vec<_>>();
+            return Err(Error::Network(messages.join(", ")));
+        }
+        response
+            .data
+            .ok_or_else(|| Error::Decode("GraphQL response without data".to_string()))
+    }
+}
+
+fn query(group: bool) -> String {
+    let (owner, filter) = match group {
+        true => ("group", "includeSubgroups: true, "),
+        false => ("project", ""),
+    };
+    format!(
+        "query($fullPath: ID!, $updatedAfter: Time, $after: String, $discussions: Int) {{
+  {owner}(fullPath: $fullPath) {{
+    mergeRequests({filter}updatedAfter: $updatedAfter, first: {MERGE_REQUESTS_PER_PAGE}, after: $after) {{{MERGE_REQUEST_FIELDS}
+    }}
+  }}
+}}"
+    )
+}
+
+/// GraphQL ids are global, e.g. `gid://gitlab/DiffNote/100`; REST ones are
+/// the number at the end.
+fn id(gid: &str) -> Result<u64, Error> {
+    gid.rsplit('/')
+        .next()
+        .and_then(|id| id.parse().ok())
+        .ok_or_else(|| Error::Decode(format!("Unexpected GraphQL id {}", gid)))
+}
+
+#[derive(Debug, Deserialize)]
+struct Response<T> {
+    data: Option<T>,
+    #[serde(default)]
+    errors: Vec<ResponseError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponseError {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Data {
+    group: Option<Owner>,
+    project: Option<Owner>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Owner {
+    merge_requests: Connection<MergeRequestNode>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Connection<T> {
+    nodes: Vec<T>,
+    page_info: PageInfo,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PageInfo {
+    end_cursor: Option<String>,
+    has_next_page: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MergeRequestNode {
+    author: AuthorNode,
+    created_at: DateTime<Utc>,
+    diff_stats_summary: Option<DiffStatsSummary>,
+    discussions: Connection<DiscussionNode>,
+    draft: bool,
+    iid: String,
+    labels: Nodes<LabelNode>,
+    merged_at: Option<DateTime<Utc>>,
+    project_id: u64,
+    reference: String,
+    state: MergeRequestState,
+    target_branch: String,
+    title: String,
+    web_url: String,
+}
+
+impl MergeRequestNode {
+    /// The merge request as REST returns it, and its discussions unless
+    /// some of them are missing from the page.
+    fn split(self) -> Result<(MergeRequest, Option<Vec<MergeRequestDiscussion>>), Error> {
+        let merge_request = MergeRequest {
+            author: self.author.try_into()?,
+            created_at: self.created_at,
+            diff_stats: self.diff_stats_summary.map(|summary| DiffStats {
+                additions: summary.additions,
+                deletions: summary.deletions,
+                files: summary.file_count,
+            }),
+            draft: self.draft,
+            iid: self
+                .iid
+                .parse()
+                .map_err(|_| Error::Decode(format!("Unexpected merge request iid {}", self.iid)))?,
+            labels: self
+                .labels
+                .nodes
+                .into_iter()
+                .map(|label| label.title)
+                .collect(),
+            merged_at: self.merged_at,
+            project_id: self.project_id,
+            references: References {
+                full: self.reference,
+            },
+            state: self.state,
+            target_branch: self.target_branch,
+            title: self.title,
+            web_url: self.web_url,
+        };
+        if self.discussions.page_info.has_next_page {
+            return Ok((merge_request, None));
+        }
+
+        let discussions = self
+            .discussions
+            .nodes
+            .into_iter()
+            .map(|discussion| {
+                let notes = discussion
+                    .notes
+                    .nodes
+                    .into_iter()
+                    .map(MergeRequestNote::try_from)
+                    .collect::<Result<_, Error>>()?;
+                Ok(MergeRequestDiscussion { notes })
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok((merge_request, Some(discussions)))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DiffStatsSummary {
+    additions: u64,
+    deletions: u64,
+    file_count: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct LabelNode {
+    title: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DiscussionNode {
+    notes: Nodes<NoteNode>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Nodes<T> {
+    nodes: Vec<T>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NoteNode {
+    author: AuthorNode,
+    award_emoji: Nodes<AwardEmojiNode>,
+    body: String,
+    created_at: DateTime<Utc>,
+    id: String,
+    position: Option<PositionNode>,
+    resolvable: bool,
+    resolved: bool,
+    resolved_at: Option<DateTime<Utc>>,
+    resolved_by: Option<AuthorNode>,
+    system: bool,
+    updated_at: DateTime<Utc>,
+}
+
+impl TryFrom<NoteNode> for MergeRequestNote {
+    type Error = Error;
+
+    fn try_from(note: NoteNode) -> Result<Self, Self::Error> {
+        Ok(MergeRequestNote {
+            award_emoji: note
+                .award_emoji
+                .nodes
+                .into_iter()
+                .map(|emoji| {
+                    Ok(AwardEmoji {
+                        name: emoji.name,
+                        user: emoji.user.try_into()?,
+                    })
+                })
+                .collect::<Result<_, Error>>()?,
+            author: note.author.try_into()?,
+            body: note.body,
+            created_at: note.created_at,
+            id: id(&note.id)?,
+            position: note.position.map(NotePosition::from),
+            resolvable: note.resolvable,
+            resolved: note.resolved,
+            resolved_at: note.resolved_at,
+            resolved_by: note.resolved_by.map(Author::try_from).transpose()?,
+            system: note.system,
+            updated_at: note.updated_at,
+        })
+    }
+}
+
+/// GraphQL does not expose the `line_range` of multiline comments, they
+/// come through as their last line only.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PositionNode {
+    diff_refs: DiffRefs,
+    new_line: Option<u64>,
+    new_path: Option<String>,
+    old_line: Option<u64>,
+    old_path: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DiffRefs {
+    head_sha: String,
+}
+
+impl From<PositionNode> for NotePosition {
+    fn from(position: PositionNode) -> Self {
+        NotePosition {
+            head_sha: position.diff_refs.head_sha,
+            line_range: None,
+            new_line: position.new_line,
+            new_path: position.new_path.unwrap_or_default(),
+            old_line: position.old_line,
+            old_path: position.old_path.unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AwardEmojiNode {
+    name: String,
+    user: AuthorNode,
+}
+
+#[derive(Debug, Deserialize)]
+struct AuthorNode {
+    id: String,
+    username: String,
+}
+
+impl TryFrom<AuthorNode> for Author {
+    type Error = Error;
+
+    fn try_from(author: AuthorNode) -> Result<Self, Self::Error> {
+        Ok(Author {
+            id: id(&author.id)?,
+            username: author.username,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{body_partial_json, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::{
+        gitlab_client::{Scope, Transport},
+        review_source::ReviewSource,
+        reviewers::Reviewers,
+        store::MemoryStore,
+        test_support::{merge_request, mount_merge_requests, note},
+    };
+
+    const WEB_URL: &str = "https://gitlab.com/group/project/-/merge_requests";
+
+    fn graphql_note(id: u64, author_id: u64, body: &str) -> serde_json::Value {
+        json!({
+            "author": { "id": format!("gid://gitlab/User/{}", author_id), "username": format!("user-{}", author_id) },
+            "awardEmoji": { "nodes": [] },
+            "body": body,
+            "createdAt": "2024-11-01T09:00:00Z",
+            "id": format!("gid://gitlab/Note/{}", id),
+            "position": null,
+            "resolvable": false,
+            "resolved": false,
+            "resolvedAt": null,
+            "resolvedBy": null,
+            "system": false,
+            "updatedAt": "2024-11-01T10:00:00Z",
+        })
+    }
+
+    fn graphql_page(
+        iid: u64,
+        author_id: u64,
+        discussions: serde_json::Value,
+        more_discussions: bool,
+        end_cursor: Option<&str>,
+    ) -> serde_json::Value {
+        json!({ "data": { "project": { "mergeRequests": {
+            "pageInfo": { "endCursor": end_cursor, "hasNextPage": end_cursor.is_some() },
+            "nodes": [{
+                "author": { "id": format!("gid://gitlab/User/{}", author_id), "username": format!("user-{}", author_id) },
+                "createdAt": "2024-10-30T10:00:00Z",
+                "diffStatsSummary": { "additions": 2, "deletions": 1, "fileCount": 1 },
+                "discussions": {
+                    "pageInfo": { "endCursor": null, "hasNextPage": more_discussions },
+                    "nodes": discussions,
+                },
+                "draft": false,
+                "iid": iid.to_string(),
+                "labels": { "nodes": [{ "title": "bug" }] },
+                "mergedAt": null,
+                "projectId": 42,
+                "reference": format!("group/project!{}", iid),
+                "state": "opened",
+                "targetBranch": "main",
+                "title": "Fix the parser",
+                "webUrl": format!("{}/{}", WEB_URL, iid),
+            }],
+        } } } })
+    }
+
+    fn client(server: &MockServer, transport: Transport) -> GitlabClient<MemoryStore> {
+        GitlabClient::new(
+            server.uri(),
+            "token".to_string(),
+            Scope::Projects(vec!["group/project".to_string()]),
+            Reviewers::NonAuthors,
+            MemoryStore::new(),
+        )
+        .with_transport(transport)
+    }
+
+    #[tokio::test]
+    async fn test_fetch_matches_rest() {
+        let server = MockServer::start().await;
+
+        let mut resolved = note(100, 2, "nit: rename");
+        resolved["position"] = json!({
+            "head_sha": "ccc",
+            "line_range": null,
+            "new_line": 12,
+            "new_path": "src/lib.rs",
+            "old_line": null,
+            "old_path": "src/lib.rs",
+        });
+        resolved["resolvable"] = json!(true);
+        resolved["resolved"] = json!(true);
+        resolved["resolved_at"] = json!("2024-11-02T10:00:00Z");
+        resolved["resolved_by"] = json!({ "id": 1, "username": "user-1" });
+        let mut system = note(110, 1, "added 1 commit");
+        system["system"] = json!(true);
+        let rest_discussions = json!([
+            { "notes": [resolved, note(101, 1, "done")] },
+            { "notes": [system] },
+            { "notes": [note(120, 1, "ready for review")] },
+        ]);
+        let rest_fallback = json!([
+            { "notes": [note(200, 2, "question: why?"), note(201, 4, "+1")] },
+        ]);
+
+        mount_merge_requests(
+            &server,
+            vec![
+                (merge_request(7, 1), rest_discussions),
+                (merge_request(8, 3), rest_fallback),
+            ],
+        )
+        .await;
+
+        let mut resolved = graphql_note(100, 2, "nit: rename");
+        resolved["id"] = json!("gid://gitlab/DiffNote/100");
+        resolved["position"] = json!({
+            "diffRefs": { "headSha": "ccc" },
+            "newLine": 12,
+            "newPath": "src/lib.rs",
+            "oldLine": null,
+            "oldPath": "src/lib.rs",
+        });
+        resolved["resolvable"] = json!(true);
+        resolved["resolved"] = json!(true);
+        resolved["resolvedAt"] = json!("2024-11-02T10:00:00Z");
+        resolved["resolvedBy"] = json!({ "id": "gid://gitlab/User/1", "username": "user-1" });
+        let mut system = graphql_note(110, 1, "added 1 commit");
+        system["system"] = json!(true);
+        let graphql_discussions = json!([
+            { "notes": { "nodes": [resolved, graphql_note(101, 1, "done")] } },
+            { "notes": { "nodes": [system] } },
+            { "notes": { "nodes": [graphql_note(120, 1, "ready for review")] } },
+        ]);
+
+        Mock::given(method("POST"))
+            .and(path("/api/graphql"))
+            .and(body_partial_json(json!({ "variables": { "after": null } })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(graphql_page(
+                7,
+                1,
+                graphql_discussions,
+                false,
+                Some("page-2"),
+            )))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/graphql"))
+            .and(body_partial_json(
+                json!({ "variables": { "after": "page-2" } }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(graphql_page(
+                8,
+                3,
+                json!([]),
+                true,
+                None,
+            )))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut rest = client(&server, Transport::Rest)
+            .fetch()
+            .await
+            .unwrap()
+            .change_requests;
+        let mut graphql = client(&server, Transport::GraphQl)
+            .fetch()
+            .await
+            .unwrap()
+            .change_requests;
+        rest.sort_by_key(|value| value.id);
+        graphql.sort_by_key(|value| value.id);
+
+        assert!(rest.iter().map(|value| value.id).collect::<Vec<_>>() == vec![100, 200]);
+        assert!(
+            graphql[0].merge_request.diff_stats
+                == Some(DiffStats {
+                    additions: 2,
+                    deletions: 1,
+                    files: 1,
+                })
+        );
+        // Only GraphQL reports the size of merge requests.
+        for change_request in &mut graphql {
+            change_request.merge_request.diff_stats = None;
+        }
+        assert!(graphql == rest);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_reactions() {
+        let server = MockServer::start().await;
+        mount_merge_requests(
+            &server,
+            vec![(
+                merge_request(7, 1),
+                json!([
+                    { "notes": [note(100, 2, "Rename\n#naming")] },
+                    { "notes": [note(200, 2, "Rename too")] },
+                ]),
+            )],
+        )
+        .await;
+        let thumbsup = json!([{ "name": "thumbsup", "user": { "id": 3, "username": "user-3" } }]);
+        Mock::given(method("GET"))
+            .and(path(
+                "/api/v4/projects/42/merge_requests/7/notes/100/award_emoji",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&thumbsup))
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(
+                "/api/v4/projects/42/merge_requests/7/notes/200/award_emoji",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&thumbsup))
+            .with_priority(1)
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let with_reaction = |id, body| {
+            let mut note = graphql_note(id, 2, body);
+            note["awardEmoji"] = json!({ "nodes": [
+                { "name": "thumbsup", "user": { "id": "gid://gitlab/User/3", "username": "user-3" } },
+            ] });
+            json!({ "notes": { "nodes": [note] } })
+        };
+        Mock::given(method("POST"))
+            .and(path("/api/graphql"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(graphql_page(
+                7,
+                1,
+                json!([
+                    with_reaction(100, "Rename\n#naming"),
+                    with_reaction(200, "Rename too"),
+                ]),
+                false,
+                None,
+            )))
+            .mount(&server)
+            .await;
+
+        for transport in [Transport::Rest, Transport::GraphQl] {
+            let change_requests = client(&server, transport)
+                .fetch()
+                .await
+                .unwrap()
+                .change_requests;
+            assert!(
+                change_requests
+                    .iter()
+                    .map(|value| (value.id, value.reactions.len()))
+                    .collect::<Vec<_>>()
+                    == vec![(100, 1), (200, 0)]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/graphql"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "data": { "project": null },
+                "errors": [{ "message": "Field 'mergeRequests' doesn't exist" }],
+            })))
+            .mount(&server)
+            .await;
+
+        let result = client(&server, Transport::GraphQl).fetch().await;
+        assert!(
+            result
+                == Err(Error::Network(
+                    "Field 'mergeRequests' doesn't exist".to_string()
+                ))
+        );
+
+        server.reset().await;
+        Mock::given(method("POST"))
+            .and(path("/api/graphql"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "data": { "project": null } })),
+            )
+            .mount(&server)
+            .await;
+
+        let result = client(&server, Transport::GraphQl).fetch().await;
+        assert!(result == Err(Error::Network("group/project was not found".to_string())));
+    }
+
+    #[test]
+    fn test_id() {
+        assert!(id("gid://gitlab/DiffNote/100") == Ok(100));
+        assert!(matches!(id("gid://gitlab/User/abc"), Err(Error::Decode(_))));
+    }
+}
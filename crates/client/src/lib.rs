@@ -1,9 +1,41 @@
-// mod ai_client;
+mod ai_client;
+mod cache;
 mod change_request;
+mod conventional_comment;
+mod error;
+mod forge;
+mod gitea_client;
+mod github_client;
 mod gitlab_client;
+mod pagination;
 mod parsed_note;
-// mod reviewer;
+mod review_source;
+mod reviewer;
+mod reviewers;
+mod scheduler;
+pub mod store;
+mod sync_policy;
+mod taxonomy;
+#[cfg(test)]
+mod test_support;
+#[cfg(feature = "webhook")]
+mod webhook;
 
-pub use change_request::ChangeRequest;
-// pub use reviewer::Reviewer;
-pub use gitlab_client::GitlabClient;
+pub use ai_client::AiClient;
+pub use change_request::{
+    ChangeRequest, DateRange, DiffPosition, DiffStats, MergeRequestMetadata, MergeRequestState,
+    NoteKind, Reaction, ReactionStats, Size, Suggestion, SuggestionStats, Tag,
+};
+pub use conventional_comment::{CommentLabel, ConventionalComment, Severity};
+pub use error::Error;
+pub use gitea_client::GiteaClient;
+pub use github_client::GithubClient;
+pub use gitlab_client::{GitlabClient, SaveOutcome, Scope, Transport};
+pub use review_source::{ReviewSource, SyncReport};
+pub use reviewer::Reviewer;
+pub use reviewers::{ReviewerId, Reviewers};
+pub use scheduler::{Progress, Scheduler};
+pub use sync_policy::SyncPolicy;
+pub use taxonomy::{TagCheck, TagIssue, Taxonomy, TaxonomyEntry};
+#[cfg(feature = "webhook")]
+pub use webhook::WebhookReceiver;
@@ -0,0 +1,254 @@
+use log::*;
+use reqwest::{header::HeaderMap, RequestBuilder, Url};
+use serde::de::DeserializeOwned;
+
+use crate::{error::Error, scheduler::Scheduler};
+
+/// Sends `request` through `scheduler` and follows GitLab pagination until
+/// the last page, concatenating every page into a single list.
+pub async fn get_all<T: DeserializeOwned>(
+    scheduler: &Scheduler,
+    request: RequestBuilder,
+) -> Result<Vec<T>, Error> {
+    get_while(scheduler, request, |_| true).await
+}
+
+/// Like [`get_all`], but stops at the first item failing `keep`, without
+/// requesting the pages after it. Meant for lists sorted so that no later
+/// item would pass either.
+pub async fn get_while<T: DeserializeOwned>(
+    scheduler: &Scheduler,
+    request: RequestBuilder,
+    keep: impl Fn(&T) -> bool,
+) -> Result<Vec<T>, Error> {
+    let (client, request) = request.build_split();
+    let mut request = request?;
+    let mut items = vec![];
+
+    loop {
+        info!("request: {}", request.url());
+        let response = scheduler
+            .execute(
+                &client,
+                request
+                    .try_clone()
+                    .expect("GET requests have no streaming body"),
+            )
+            .await?;
+        let next = next_page(request.url(), response.headers());
+        let page = response.json::<Vec<T>>().await?;
+        let count = page.len();
+        let kept = page.into_iter().take_while(&keep).collect::<Vec<_>>();
+        let stopped = kept.len() < count;
+        items.extend(kept);
+
+        match next {
+            Some(url) if !stopped => *request.url_mut() = url,
+            _ => break,
+        }
+    }
+
+    Ok(items)
+}
+
+/// Resolves the next page from the `Link` header, falling back to `X-Next-Page`.
+fn next_page(url: &Url, headers: &HeaderMap) -> Option<Url> {
+    let link = headers
+        .get("link")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            value.split(',').find_map(|link| {
+                let (target, params) = link.split_once(';')?;
+                params
+                    .split(';')
+                    .any(|param| param.trim() == r#"rel="next""#)
+                    .then(|| target.trim().trim_start_matches('<').trim_end_matches('>'))
+            })
+        })
+        .and_then(|target| Url::parse(target).ok());
+
+    if link.is_some() {
+        return link;
+    }
+
+    let page = headers
+        .get("x-next-page")
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())?;
+
+    let mut next = url.clone();
+    let query = url
+        .query_pairs()
+        .filter(|(key, _)| key != "page")
+        .collect::<Vec<_>>();
+    next.query_pairs_mut()
+        .clear()
+        .extend_pairs(query)
+        .append_pair("page", page);
+    Some(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{method, path, query_param, query_param_is_missing},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_next_page() {
+        let url = Url::parse("https://gitlab.com/api/v4/items?per_page=2&page=1").unwrap();
+
+        let mut headers = HeaderMap::new();
+        assert!(next_page(&url, &headers).is_none());
+
+        headers.insert("x-next-page", "".parse().unwrap());
+        assert!(next_page(&url, &headers).is_none());
+
+        headers.insert("x-next-page", "2".parse().unwrap());
+        assert!(
+            next_page(&url, &headers).unwrap().as_str()
+                == "https://gitlab.com/api/v4/items?per_page=2&page=2"
+        );
+
+        headers.insert(
+            "link",
+            r#"<https://gitlab.com/api/v4/items?id_after=42>; rel="next", <https://gitlab.com/api/v4/items?page=1>; rel="first""#
+                .parse()
+                .unwrap(),
+        );
+        assert!(
+            next_page(&url, &headers).unwrap().as_str()
+                == "https://gitlab.com/api/v4/items?id_after=42"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_all() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/items"))
+            .and(query_param_is_missing("page"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json([1, 2])
+                    .insert_header("x-next-page", "2"),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/items"))
+            .and(query_param("page", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json([3]).insert_header(
+                "link",
+                format!(r#"<{}/items?page=3>; rel="next""#, server.uri()),
+            ))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/items"))
+            .and(query_param("page", "3"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json([4, 5])
+                    .insert_header("x-next-page", ""),
+            )
+            .mount(&server)
+            .await;
+
+        let request = reqwest::Client::new().get(format!("{}/items", server.uri()));
+        let items = get_all::<u64>(&Scheduler::default(), request)
+            .await
+            .unwrap();
+        assert!(items == vec![1, 2, 3, 4, 5]);
+    }
+
+    #[tokio::test]
+    async fn test_get_while() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/items"))
+            .and(query_param_is_missing("page"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json([5, 4])
+                    .insert_header("x-next-page", "2"),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/items"))
+            .and(query_param("page", "2"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json([3, 2])
+                    .insert_header("x-next-page", "3"),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/items"))
+            .and(query_param("page", "3"))
+            .respond_with(ResponseTemplate::new(200).set_body_json([1]))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let request = reqwest::Client::new().get(format!("{}/items", server.uri()));
+        let items = get_while::<u64>(&Scheduler::default(), request, |item| *item > 2)
+            .await
+            .unwrap();
+        assert!(items == vec![5, 4, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_get_all_errors() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/unauthorized"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/throttled"))
+            .respond_with(ResponseTemplate::new(429).insert_header("retry-after", "30"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/garbage"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("<html>"))
+            .mount(&server)
+            .await;
+
+        // Without retries, so that the rate limit error surfaces right away.
+        let scheduler = Scheduler::default().max_retries(0);
+        let client = reqwest::Client::new();
+        let result = get_all::<u64>(
+            &scheduler,
+            client.get(format!("{}/unauthorized", server.uri())),
+        )
+        .await;
+        assert!(result == Err(Error::Auth));
+
+        let result = get_all::<u64>(
+            &scheduler,
+            client.get(format!("{}/throttled", server.uri())),
+        )
+        .await;
+        assert!(
+            result
+                == Err(Error::RateLimit {
+                    retry_after: Some(30)
+                })
+        );
+
+        let result =
+            get_all::<u64>(&scheduler, client.get(format!("{}/garbage", server.uri()))).await;
+        assert!(matches!(result, Err(Error::Decode(_))));
+    }
+}
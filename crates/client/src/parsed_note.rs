@@ -1,56 +1,131 @@
 use std::fmt;
 
 use regex::Regex;
-use serde::Deserialize;
 
-use crate::change_request::ChangeRequest;
+use crate::{
+    change_request::{ChangeRequest, Suggestion, Tag},
+    conventional_comment::{ConventionalComment, Severity},
+    taxonomy::Taxonomy,
+};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug)]
 pub struct ParsedNote {
+    pub conventional_comment: Option<ConventionalComment>,
     pub description: String,
-    pub category: Option<String>,
-    pub sub_category: Option<String>,
+    pub severity: Option<Severity>,
+    /// Suggestion blocks are also left in the description, so that writing
+    /// the note back keeps them.
+    pub suggestions: Vec<Suggestion>,
+    /// The hashtags ending the note, e.g. `#security/injection #testing/unit`.
+    pub tags: Vec<Tag>,
+    /// `tags` as written, whatever [`ParsedNote::normalize`] made of them.
+    pub original_tags: Vec<Tag>,
+}
+
+impl ParsedNote {
+    /// Rewrites the tags to the spelling of `taxonomy`, if any.
+    pub fn normalize(mut self, taxonomy: Option<&Taxonomy>) -> Self {
+        if let Some(taxonomy) = taxonomy {
+            self.tags = taxonomy.normalize(self.tags);
+        }
+        self
+    }
 }
 
 impl From<String> for ParsedNote {
     fn from(str: String) -> Self {
         let str = str.trim();
-        let re = Regex::new(r"(?U)^([\s\S]*)(\#([A-z-_]+)\/([A-z-_]+))?$").unwrap();
-        let caps = re.captures(str).unwrap();
+        // The tag block is the longest run of hashtags ending the note,
+        // starting a line of its own so that a sentence ending with a hashtag
+        // keeps it. Tags start with a letter, unlike references such as `#42`.
+        let tag = r"\#(\p{L}[\w-]*(?:/[\w-]+)*)";
+        let block = Regex::new(&format!(r"(?:^|\n)[^\S\n]*({tag}(?:\s+{tag})*)$")).unwrap();
+        let tag = Regex::new(tag).unwrap();
 
-        let description = caps.get(1).unwrap().as_str().to_string();
-        let category = caps.get(3).map(|m| m.as_str().to_string());
-        let sub_category = caps.get(4).map(|m| m.as_str().to_string());
+        let (description, tags) = match block.captures(str).and_then(|caps| caps.get(1)) {
+            Some(block) => {
+                let mut tags = vec![];
+                for caps in tag.captures_iter(block.as_str()) {
+                    let tag = Tag::from(&caps[1]);
+                    if !tags.contains(&tag) {
+                        tags.push(tag);
+                    }
+                }
+                (str[..block.start()].trim_end(), tags)
+            }
+            None => (str, vec![]),
+        };
 
+        let conventional_comment = ConventionalComment::parse(description);
         ParsedNote {
-            description,
-            category,
-            sub_category,
+            severity: conventional_comment
+                .as_ref()
+                .map(ConventionalComment::severity),
+            conventional_comment,
+            description: description.to_string(),
+            original_tags: tags.clone(),
+            suggestions: suggestions(str),
+            tags,
+        }
+    }
+}
+
+/// Finds the ```` ```suggestion ```` blocks of a note. Like GitLab, blocks
+/// fenced with more backticks may contain fences of their own.
+fn suggestions(str: &str) -> Vec<Suggestion> {
+    let mut suggestions = vec![];
+    let mut lines = str.lines();
+    while let Some(line) = lines.next() {
+        let line = line.trim();
+        let fence = &line[..line.len() - line.trim_start_matches('`').len()];
+        let Some(offsets) = line[fence.len()..].strip_prefix("suggestion") else {
+            continue;
+        };
+        if fence.len() < 3 {
+            continue;
         }
+        let (lines_above, lines_below) = offsets
+            .trim()
+            .strip_prefix(":-")
+            .and_then(|offsets| offsets.split_once('+'))
+            .and_then(|(above, below)| Some((above.parse().ok()?, below.parse().ok()?)))
+            .unwrap_or((0, 0));
+        let code = lines
+            .by_ref()
+            .take_while(|line| line.trim() != fence)
+            .collect::<Vec<_>>()
+            .join("\n");
+        suggestions.push(Suggestion {
+            applied: None,
+            code,
+            lines_above,
+            lines_below,
+        });
     }
+    suggestions
 }
 
 impl From<&ChangeRequest> for ParsedNote {
     fn from(change_request: &ChangeRequest) -> Self {
         ParsedNote {
+            conventional_comment: change_request.conventional_comment.clone(),
             description: change_request.description.clone(),
-            category: change_request.category.clone(),
-            sub_category: change_request.sub_category.clone(),
+            original_tags: change_request.original_tags.clone(),
+            severity: change_request.severity,
+            suggestions: change_request.suggestions.clone(),
+            tags: change_request.tags.clone(),
         }
     }
 }
 
 impl fmt::Display for ParsedNote {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match (&self.description, &self.category, &self.sub_category) {
-            (description, Some(category), None) => {
-                write!(f, "{}  \n#{}/other", description, category)
-            }
-            (description, Some(category), Some(sub_category)) => {
-                write!(f, "{}  \n#{}/{}", description, category, sub_category)
-            }
-            (description, _, _) => write!(f, "{}", description),
+        write!(f, "{}", self.description)?;
+        for (index, tag) in self.tags.iter().enumerate() {
+            let separator = if index == 0 { "  \n" } else { " " };
+            write!(f, "{}#{}", separator, tag)?;
         }
+        Ok(())
     }
 }
 
@@ -62,21 +137,95 @@ mod tests {
     fn test_merge_request_note_into() {
         let parsed = ParsedNote::from("comment".to_string());
         assert!(parsed.description == "comment");
-        assert!(parsed.category == None);
-        assert!(parsed.sub_category == None);
+        assert!(parsed.tags.is_empty());
 
         let parsed = ParsedNote::from(
             r#"comment
             #category/sub_category"#
                 .to_string(),
         );
-        assert!(parsed.description == "comment\n            ");
-        assert!(parsed.category == Some("category".to_string()));
-        assert!(parsed.sub_category == Some("sub_category".to_string()));
+        assert!(parsed.description == "comment");
+        assert!(parsed.tags == vec![Tag::from("category/sub_category")]);
 
         let parsed = ParsedNote::from("added 1 commit\n\n<ul><li>655de802 - fix: various fix and improvements</li></ul>\n\n[Compare with previous version](/archipels-managed/connect-monorepo/-/merge_requests/1317/diffs?diff_id=1170847463&start_sha=22dde424204c6a05cfd3fc11c7958391fbe5a12a)".to_string());
         assert!(parsed.description == "added 1 commit\n\n<ul><li>655de802 - fix: various fix and improvements</li></ul>\n\n[Compare with previous version](/archipels-managed/connect-monorepo/-/merge_requests/1317/diffs?diff_id=1170847463&start_sha=22dde424204c6a05cfd3fc11c7958391fbe5a12a)");
-        assert!(parsed.category == None);
-        assert!(parsed.sub_category == None);
+        assert!(parsed.tags.is_empty());
+    }
+
+    #[test]
+    fn test_multiple_tags() {
+        let parsed = ParsedNote::from(
+            "Escape the query, see #security/sql and #42\n#security/injection #testing/unit\n#security/injection"
+                .to_string(),
+        );
+        assert!(parsed.description == "Escape the query, see #security/sql and #42");
+        assert!(
+            parsed
+                .tags
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                == vec!["security/injection", "testing/unit"]
+        );
+        assert!(
+            parsed.to_string()
+                == "Escape the query, see #security/sql and #42  \n#security/injection #testing/unit"
+        );
+    }
+
+    #[test]
+    fn test_nested_tags() {
+        let parsed =
+            ParsedNote::from("Use a prepared statement\n#security/injection/sql #perf".to_string());
+        assert!(
+            parsed
+                .tags
+                .iter()
+                .map(|tag| tag.path.len())
+                .collect::<Vec<_>>()
+                == vec![3, 1]
+        );
+        assert!(parsed.to_string() == "Use a prepared statement  \n#security/injection/sql #perf");
+        assert!(ParsedNote::from(parsed.to_string()).tags == parsed.tags);
+
+        let parsed = ParsedNote::from("Same as #42".to_string());
+        assert!(parsed.tags.is_empty());
+
+        let parsed = ParsedNote::from("Ask the owners, see #backend".to_string());
+        assert!(parsed.description == "Ask the owners, see #backend");
+        assert!(parsed.tags.is_empty());
+        let parsed = ParsedNote::from("Ask the owners, see #backend\n  #process".to_string());
+        assert!(parsed.description == "Ask the owners, see #backend");
+        assert!(parsed.tags == vec![Tag::from("process")]);
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let parsed = ParsedNote::from(
+            "Prefer early returns:\n```suggestion:-1+2\nif !ready {\n    return;\n}\n```\n\nand\n````suggestion\n```rust\nrun();\n```\n````\n#style/control-flow"
+                .to_string(),
+        );
+        assert!(parsed.tags[0].category() == "style");
+        assert!(parsed.description.contains("```suggestion:-1+2"));
+        assert!(
+            parsed.suggestions
+                == vec![
+                    Suggestion {
+                        applied: None,
+                        code: "if !ready {\n    return;\n}".to_string(),
+                        lines_above: 1,
+                        lines_below: 2,
+                    },
+                    Suggestion {
+                        applied: None,
+                        code: "```rust\nrun();\n```".to_string(),
+                        lines_above: 0,
+                        lines_below: 0,
+                    },
+                ]
+        );
+
+        let parsed = ParsedNote::from("```rust\nlet suggestion = 1;\n```".to_string());
+        assert!(parsed.suggestions.is_empty());
     }
 }
@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{change_request::ChangeRequest, error::Error};
+
+/// The outcome of a sync: whatever could be fetched, plus the errors of the
+/// merge requests that could not.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncReport {
+    pub change_requests: Vec<ChangeRequest>,
+    pub errors: Vec<Error>,
+}
+
+/// A code-review platform that change requests can be fetched from.
+#[allow(async_fn_in_trait)]
+pub trait ReviewSource {
+    async fn fetch(&self) -> Result<SyncReport, Error>;
+
+    /// Drops the cache and syncs everything again from the start date of the
+    /// sync policy.
+    async fn rebuild(&self) -> Result<SyncReport, Error>;
+}
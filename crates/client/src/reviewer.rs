@@ -1,35 +1,135 @@
-use crate::{ai_client::AiClient, change_request::ChangeRequest, gitlab_client::GitlabClient};
+use log::*;
 
-pub struct Reviewer {
+use crate::{
+    ai_client::AiClient,
+    error::Error,
+    gitlab_client::{GitlabClient, SaveOutcome},
+    review_source::{ReviewSource, SyncReport},
+    store::Store,
+};
+
+pub struct Reviewer<S> {
     ai_client: AiClient,
-    gitlab_client: GitlabClient,
+    gitlab_client: GitlabClient<S>,
 }
 
-impl Reviewer {
-    pub async fn new() -> Self {
-        let ai_client = AiClient::new();
-        let gitlab_client = GitlabClient::new().await;
+impl<S: Store> Reviewer<S> {
+    pub fn new(ai_client: AiClient, gitlab_client: GitlabClient<S>) -> Self {
         Self {
             ai_client,
             gitlab_client,
         }
     }
 
-    pub async fn fetch(&self) -> Vec<ChangeRequest> {
+    pub async fn fetch(&self) -> Result<SyncReport, Error> {
         self.gitlab_client.fetch().await
     }
 
-    pub async fn review(&self) {
-        let change_requests = self.gitlab_client.fetch().await;
-        for mut change_request in change_requests {
-            match (&change_request.category, &change_request.sub_category) {
-                (None, None) => {
-                    self.ai_client.categorize(&mut change_request).await;
-                    self.gitlab_client.save(&change_request).await;
-                    println!("UPDATED: {:?}", change_request);
-                }
-                _ => {}
+    /// Categorizes the untagged change requests and writes their tags back to
+    /// GitLab, returning the outcome of each note by url. A note that fails
+    /// does not stop the others.
+    pub async fn review(&self) -> Result<Vec<(String, Result<SaveOutcome, Error>)>, Error> {
+        let report = self.gitlab_client.fetch().await?;
+        let mut outcomes = vec![];
+        for mut change_request in report.change_requests {
+            if !change_request.tags.is_empty() {
+                continue;
+            }
+            let outcome = match self.ai_client.categorize(&mut change_request).await {
+                Ok(()) => self.gitlab_client.save(&change_request).await,
+                Err(error) => Err(error),
+            };
+            if let Err(error) = &outcome {
+                warn!("Failed to review {}: {}", change_request.url, error);
             }
+            outcomes.push((change_request.url, outcome));
         }
+        Ok(outcomes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+    use crate::{
+        gitlab_client::Scope,
+        reviewers::Reviewers,
+        store::MemoryStore,
+        taxonomy::Taxonomy,
+        test_support::{mount, note},
+    };
+
+    #[tokio::test]
+    async fn test_review() {
+        let server = MockServer::start().await;
+        mount(
+            &server,
+            json!([
+                { "notes": [note(100, 2, "first")] },
+                { "notes": [note(200, 2, "second")] },
+                { "notes": [note(300, 2, "tagged\n#testing")] },
+            ]),
+        )
+        .await;
+        let categorized = json!({
+            "description": "",
+            "category": "testing",
+            "sub_category": "unit",
+        });
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "choices": [{ "message": { "content": categorized.to_string() } }],
+            })))
+            .expect(2)
+            .mount(&server)
+            .await;
+        let endpoint = "/api/v4/projects/group%2Fproject/merge_requests/7/notes/100";
+        Mock::given(method("GET"))
+            .and(path(endpoint))
+            .respond_with(ResponseTemplate::new(200).set_body_json(note(100, 2, "first")))
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path(endpoint))
+            .respond_with(ResponseTemplate::new(200).set_body_json({
+                let mut note = note(100, 2, "first  \n#testing/unit");
+                note["updated_at"] = json!("2024-11-02T10:00:00Z");
+                note
+            }))
+            .mount(&server)
+            .await;
+
+        let taxonomy = Taxonomy::from_toml("version = 1\n[categories.testing]").unwrap();
+        let reviewer = Reviewer::new(
+            AiClient::new("key".to_string(), taxonomy).with_base_url(server.uri()),
+            GitlabClient::new(
+                server.uri(),
+                "token".to_string(),
+                Scope::Projects(vec!["group/project".to_string()]),
+                Reviewers::NonAuthors,
+                MemoryStore::new(),
+            ),
+        );
+
+        // Note 200 cannot be read back, which does not keep note 100 from
+        // being saved.
+        let outcomes = reviewer.review().await.unwrap();
+        assert!(outcomes.len() == 2);
+        assert!(outcomes[0].0.ends_with("#note_100"));
+        assert!(
+            outcomes[0].1.as_ref().unwrap()
+                == &SaveOutcome::Saved {
+                    updated_at: "2024-11-02T10:00:00Z".parse().unwrap()
+                }
+        );
+        assert!(outcomes[1].0.ends_with("#note_200"));
+        assert!(matches!(outcomes[1].1, Err(Error::Network(_))));
     }
 }
@@ -0,0 +1,105 @@
+use std::{convert::Infallible, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::gitlab_client::Author;
+
+/// A GitLab user, identified either by numeric id or by username.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReviewerId {
+    Id(u64),
+    Username(String),
+}
+
+impl ReviewerId {
+    fn matches(&self, author: &Author) -> bool {
+        match self {
+            ReviewerId::Id(id) => *id == author.id,
+            ReviewerId::Username(username) => *username == author.username,
+        }
+    }
+}
+
+/// Whose discussions are kept as change requests.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Reviewers {
+    /// Only discussions started by one of these users.
+    Only(Vec<ReviewerId>),
+    /// Any discussion not started by the merge request author.
+    NonAuthors,
+}
+
+impl Reviewers {
+    pub(crate) fn matches(&self, note_author: &Author, merge_request_author: &Author) -> bool {
+        match self {
+            Reviewers::Only(reviewers) => reviewers.iter().any(|r| r.matches(note_author)),
+            Reviewers::NonAuthors => note_author.id != merge_request_author.id,
+        }
+    }
+}
+
+/// Parses a comma-separated list of ids or usernames, or `*` for every non-author.
+impl FromStr for Reviewers {
+    type Err = Infallible;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        let str = str.trim();
+        if str == "*" {
+            return Ok(Reviewers::NonAuthors);
+        }
+
+        let reviewers = str
+            .split(',')
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(|value| match value.parse::<u64>() {
+                Ok(id) => ReviewerId::Id(id),
+                Err(_) => ReviewerId::Username(value.to_string()),
+            })
+            .collect();
+
+        Ok(Reviewers::Only(reviewers))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reviewers_from_str() {
+        let reviewers: Reviewers = "20796726, alice,,".parse().unwrap();
+        assert!(
+            reviewers
+                == Reviewers::Only(vec![
+                    ReviewerId::Id(20796726),
+                    ReviewerId::Username("alice".to_string()),
+                ])
+        );
+
+        let reviewers: Reviewers = " * ".parse().unwrap();
+        assert!(reviewers == Reviewers::NonAuthors);
+    }
+
+    #[test]
+    fn test_reviewers_matches() {
+        let alice = Author {
+            id: 1,
+            username: "alice".to_string(),
+        };
+        let bob = Author {
+            id: 2,
+            username: "bob".to_string(),
+        };
+
+        let reviewers = Reviewers::Only(vec![ReviewerId::Username("alice".to_string())]);
+        assert!(reviewers.matches(&alice, &bob));
+        assert!(!reviewers.matches(&bob, &alice));
+
+        let reviewers = Reviewers::Only(vec![ReviewerId::Id(2)]);
+        assert!(reviewers.matches(&bob, &alice));
+
+        assert!(Reviewers::NonAuthors.matches(&alice, &bob));
+        assert!(!Reviewers::NonAuthors.matches(&alice, &alice));
+    }
+}
@@ -0,0 +1,344 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_lock::Semaphore;
+use chrono::{DateTime, TimeZone, Utc};
+use futures_timer::Delay;
+use log::*;
+use reqwest::{header::HeaderMap, Client, Request, RequestBuilder, Response, StatusCode};
+
+use crate::error::Error;
+
+/// How many merge requests of the current sync have been processed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    pub done: usize,
+    pub total: usize,
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.done, self.total)
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    paused_until: Option<DateTime<Utc>>,
+    progress: Progress,
+}
+
+/// Sends every request of a client: bounds how many are in flight, pauses
+/// when the server says the rate limit is spent, and retries transient
+/// failures with exponential backoff and jitter. Clones share their limits.
+#[derive(Clone)]
+pub struct Scheduler {
+    base_delay: Duration,
+    max_retries: u32,
+    on_progress: Option<Arc<dyn Fn(Progress) + Send + Sync>>,
+    semaphore: Arc<Semaphore>,
+    state: Arc<Mutex<State>>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new(4)
+    }
+}
+
+impl Scheduler {
+    /// Allows at most `concurrency` requests in flight at once.
+    pub fn new(concurrency: usize) -> Self {
+        Self {
+            base_delay: Duration::from_millis(500),
+            max_retries: 5,
+            on_progress: None,
+            semaphore: Arc::new(Semaphore::new(concurrency.max(1))),
+            state: Arc::default(),
+        }
+    }
+
+    /// The first retry waits about `base_delay`, and each one after twice as long.
+    pub fn base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Called every time a merge request is added to or done with the sync.
+    pub fn on_progress(mut self, on_progress: impl Fn(Progress) + Send + Sync + 'static) -> Self {
+        self.on_progress = Some(Arc::new(on_progress));
+        self
+    }
+
+    pub fn progress(&self) -> Progress {
+        self.state.lock().unwrap().progress
+    }
+
+    pub(crate) fn reset_progress(&self) {
+        self.update_progress(|progress| *progress = Progress::default());
+    }
+
+    pub(crate) fn add_total(&self, total: usize) {
+        self.update_progress(|progress| progress.total += total);
+    }
+
+    pub(crate) fn advance(&self) {
+        self.update_progress(|progress| progress.done += 1);
+    }
+
+    fn update_progress(&self, update: impl FnOnce(&mut Progress)) {
+        let progress = {
+            let mut state = self.state.lock().unwrap();
+            update(&mut state.progress);
+            state.progress
+        };
+        if let Some(on_progress) = &self.on_progress {
+            on_progress(progress);
+        }
+    }
+
+    pub(crate) async fn send(&self, request: RequestBuilder) -> Result<Response, Error> {
+        let (client, request) = request.build_split();
+        self.execute(&client, request?).await
+    }
+
+    /// Sends `request`, retrying it while it fails transiently, and maps the
+    /// final response onto an [`Error`] if it is not a success.
+    pub(crate) async fn execute(
+        &self,
+        client: &Client,
+        request: Request,
+    ) -> Result<Response, Error> {
+        let mut attempt = 0;
+
+        loop {
+            self.wait().await;
+
+            let result = {
+                let _permit = self.semaphore.acquire().await;
+                let request = request
+                    .try_clone()
+                    .expect("requests have no streaming body");
+                client.execute(request).await
+            };
+
+            let retry_after = match &result {
+                Ok(response) => {
+                    self.observe(response.headers());
+                    is_transient(response).then(|| retry_after(response.headers()))
+                }
+                Err(error) => is_transient_error(error).then_some(None),
+            };
+
+            match retry_after {
+                Some(retry_after) if attempt < self.max_retries => {
+                    let delay = retry_after.unwrap_or_else(|| self.backoff(attempt));
+                    warn!(
+                        "Retrying {} in {:?} (attempt {})",
+                        request.url(),
+                        delay,
+                        attempt + 1
+                    );
+                    Delay::new(delay).await;
+                    attempt += 1;
+                }
+                _ => return Error::check(result?),
+            }
+        }
+    }
+
+    /// Waits until the rate limit window reported by the server is over.
+    async fn wait(&self) {
+        loop {
+            let paused_until = self.state.lock().unwrap().paused_until;
+            match paused_until.and_then(|until| (until - Utc::now()).to_std().ok()) {
+                Some(delay) if !delay.is_zero() => Delay::new(delay).await,
+                _ => return,
+            }
+        }
+    }
+
+    /// Pauses every request once the server reports the quota as spent.
+    fn observe(&self, headers: &HeaderMap) {
+        let remaining = header(headers, &["ratelimit-remaining", "x-ratelimit-remaining"]);
+        if remaining != Some(0) {
+            return;
+        }
+        let reset = header(headers, &["ratelimit-reset", "x-ratelimit-reset"])
+            .and_then(|reset| Utc.timestamp_opt(reset as i64, 0).single());
+        if let Some(reset) = reset {
+            info!("Rate limit spent, pausing until {}", reset);
+            self.state.lock().unwrap().paused_until = Some(reset);
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self.base_delay * 2u32.saturating_pow(attempt);
+        // Spread retries over up to half the delay so that concurrent
+        // requests do not all come back at the same time.
+        let jitter = f64::from(Utc::now().timestamp_subsec_nanos() % 1000) / 2000.0;
+        delay.mul_f64(1.0 + jitter)
+    }
+}
+
+fn is_transient(response: &Response) -> bool {
+    let exhausted = header(response.headers(), &["x-ratelimit-remaining"]) == Some(0);
+    match response.status() {
+        StatusCode::TOO_MANY_REQUESTS => true,
+        StatusCode::FORBIDDEN => exhausted,
+        status => status.is_server_error(),
+    }
+}
+
+/// Browsers do not tell connection failures apart from other failed
+/// requests, so every failed request is retried there.
+fn is_transient_error(error: &reqwest::Error) -> bool {
+    #[cfg(not(target_arch = "wasm32"))]
+    let failed = error.is_connect();
+    #[cfg(target_arch = "wasm32")]
+    let failed = error.is_request();
+    failed || error.is_timeout()
+}
+
+/// Reads `Retry-After` as either a number of seconds or an HTTP date.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    header(headers, &["retry-after"])
+        .map(Duration::from_secs)
+        .or_else(|| {
+            let value = headers.get("retry-after")?.to_str().ok()?;
+            let date = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+            Some(
+                (date.with_timezone(&Utc) - Utc::now())
+                    .to_std()
+                    .unwrap_or_default(),
+            )
+        })
+}
+
+fn header(headers: &HeaderMap, names: &[&str]) -> Option<u64> {
+    names
+        .iter()
+        .find_map(|name| headers.get(*name))
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use futures::future::join_all;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    fn scheduler() -> Scheduler {
+        Scheduler::new(2).base_delay(Duration::from_millis(10))
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_failures() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/flaky"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/flaky"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let request = reqwest::Client::new().get(format!("{}/flaky", server.uri()));
+        let response = scheduler().send(request).await.unwrap();
+        assert!(response.status() == StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_retries() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/throttled"))
+            .respond_with(ResponseTemplate::new(429))
+            .expect(3)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/unauthorized"))
+            .respond_with(ResponseTemplate::new(401))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let scheduler = scheduler().max_retries(2);
+        let client = reqwest::Client::new();
+        let result = scheduler
+            .send(client.get(format!("{}/throttled", server.uri())))
+            .await;
+        assert!(matches!(result, Err(Error::RateLimit { .. })));
+
+        let result = scheduler
+            .send(client.get(format!("{}/unauthorized", server.uri())))
+            .await;
+        assert!(matches!(result, Err(Error::Auth)));
+    }
+
+    #[tokio::test]
+    async fn test_bounds_concurrency() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/slow"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(100)))
+            .mount(&server)
+            .await;
+
+        let scheduler = scheduler();
+        let client = reqwest::Client::new();
+        let start = Instant::now();
+        join_all((0..4).map(|_| scheduler.send(client.get(format!("{}/slow", server.uri())))))
+            .await;
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let retry_after = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("retry-after", value.parse().unwrap());
+            retry_after(&headers)
+        };
+        assert!(retry_after("120") == Some(Duration::from_secs(120)));
+        assert!(retry_after("Wed, 21 Oct 2015 07:28:00 GMT") == Some(Duration::ZERO));
+        let later = (Utc::now() + chrono::TimeDelta::minutes(10)).to_rfc2822();
+        assert!(retry_after(&later).is_some_and(|delay| delay > Duration::from_secs(500)));
+        assert!(retry_after("soon").is_none());
+    }
+
+    #[test]
+    fn test_progress() {
+        let reported = Arc::new(Mutex::new(vec![]));
+        let scheduler = Scheduler::default().on_progress({
+            let reported = reported.clone();
+            move |progress| reported.lock().unwrap().push(progress.to_string())
+        });
+
+        scheduler.add_total(2);
+        scheduler.advance();
+        scheduler.advance();
+        assert!(scheduler.progress() == Progress { done: 2, total: 2 });
+        assert!(*reported.lock().unwrap() == vec!["0/2", "1/2", "2/2"]);
+    }
+}
@@ -0,0 +1,25 @@
+#[cfg(feature = "file")]
+mod file;
+#[cfg(feature = "indexed-db")]
+mod indexed_db;
+#[cfg(feature = "local-storage")]
+mod local_storage;
+mod memory;
+
+#[cfg(feature = "file")]
+pub use file::FileStore;
+#[cfg(feature = "indexed-db")]
+pub use indexed_db::IndexedDbStore;
+#[cfg(feature = "local-storage")]
+pub use local_storage::LocalStore;
+pub use memory::MemoryStore;
+
+use crate::error::Error;
+
+/// A key-value backend for the change-request cache. Values are JSON documents.
+#[allow(async_fn_in_trait)]
+pub trait Store {
+    async fn load(&self, key: &str) -> Result<Option<String>, Error>;
+    async fn save(&self, key: &str, value: String) -> Result<(), Error>;
+    async fn remove(&self, key: &str) -> Result<(), Error>;
+}
@@ -0,0 +1,79 @@
+use std::{fs, io::ErrorKind, path::PathBuf};
+
+use super::Store;
+use crate::error::Error;
+
+/// Stores each key as a JSON file inside a directory, for native targets.
+#[derive(Debug, Clone)]
+pub struct FileStore {
+    directory: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.directory.join(format!("{}.json", key))
+    }
+}
+
+impl Store for FileStore {
+    async fn load(&self, key: &str) -> Result<Option<String>, Error> {
+        match fs::read_to_string(self.path(key)) {
+            Ok(value) => Ok(Some(value)),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(Error::Storage(error.to_string())),
+        }
+    }
+
+    async fn save(&self, key: &str, value: String) -> Result<(), Error> {
+        // Keys such as `gitlab/project/group/project` nest into subdirectories.
+        let path = self.path(key);
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).map_err(|error| Error::Storage(error.to_string()))?;
+        }
+        // Write then rename so that a crash never leaves a truncated cache behind.
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, value).map_err(|error| Error::Storage(error.to_string()))?;
+        fs::rename(&tmp, &path).map_err(|error| Error::Storage(error.to_string()))
+    }
+
+    async fn remove(&self, key: &str) -> Result<(), Error> {
+        match fs::remove_file(self.path(key)) {
+            Err(error) if error.kind() != ErrorKind::NotFound => {
+                Err(Error::Storage(error.to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_store() {
+        let directory = tempfile::tempdir().unwrap();
+        let store = FileStore::new(directory.path().join("cache"));
+        assert!(store.load("key").await.unwrap().is_none());
+
+        store.save("key", "value".to_string()).await.unwrap();
+        store.save("key", "other".to_string()).await.unwrap();
+        assert!(store.load("key").await.unwrap() == Some("other".to_string()));
+
+        store.remove("key").await.unwrap();
+        store.remove("key").await.unwrap();
+        assert!(store.load("key").await.unwrap().is_none());
+
+        store
+            .save("gitlab/group/project", "value".to_string())
+            .await
+            .unwrap();
+        assert!(store.load("gitlab/group/project").await.unwrap() == Some("value".to_string()));
+    }
+}
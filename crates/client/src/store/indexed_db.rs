@@ -0,0 +1,85 @@
+use indexed_db_futures::{prelude::*, web_sys::DomException};
+use wasm_bindgen::JsValue;
+
+use super::Store;
+use crate::error::Error;
+
+const OBJECT_STORE: &str = "cache";
+
+/// An IndexedDB database, which is not subject to the `localStorage` quota.
+#[derive(Debug, Clone)]
+pub struct IndexedDbStore {
+    database: String,
+}
+
+impl IndexedDbStore {
+    pub fn new(database: impl Into<String>) -> Self {
+        Self {
+            database: database.into(),
+        }
+    }
+
+    async fn open(&self) -> Result<IdbDatabase, Error> {
+        let mut request = IdbDatabase::open_u32(&self.database, 1).map_err(dom_error)?;
+        request.set_on_upgrade_needed(Some(|event: &IdbVersionChangeEvent| {
+            if !event
+                .db()
+                .object_store_names()
+                .any(|name| name == OBJECT_STORE)
+            {
+                event.db().create_object_store(OBJECT_STORE)?;
+            }
+            Ok(())
+        }));
+        request.await.map_err(dom_error)
+    }
+
+    async fn write(
+        &self,
+        action: impl FnOnce(&IdbObjectStore) -> Result<(), DomException>,
+    ) -> Result<(), Error> {
+        let database = self.open().await?;
+        let transaction = database
+            .transaction_on_one_with_mode(OBJECT_STORE, IdbTransactionMode::Readwrite)
+            .map_err(dom_error)?;
+        let store = transaction.object_store(OBJECT_STORE).map_err(dom_error)?;
+        action(&store).map_err(dom_error)?;
+        transaction.await.into_result().map_err(dom_error)
+    }
+}
+
+impl Store for IndexedDbStore {
+    async fn load(&self, key: &str) -> Result<Option<String>, Error> {
+        let database = self.open().await?;
+        let transaction = database
+            .transaction_on_one(OBJECT_STORE)
+            .map_err(dom_error)?;
+        let store = transaction.object_store(OBJECT_STORE).map_err(dom_error)?;
+        let value = store
+            .get_owned(key)
+            .map_err(dom_error)?
+            .await
+            .map_err(dom_error)?;
+        Ok(value.and_then(|value| value.as_string()))
+    }
+
+    async fn save(&self, key: &str, value: String) -> Result<(), Error> {
+        self.write(|store| {
+            store.put_key_val_owned(key, &JsValue::from_str(&value))?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn remove(&self, key: &str) -> Result<(), Error> {
+        self.write(|store| {
+            store.delete_owned(key)?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+fn dom_error(error: DomException) -> Error {
+    Error::Storage(error.message())
+}
@@ -0,0 +1,27 @@
+use gloo_storage::{LocalStorage, Storage};
+
+use super::Store;
+use crate::error::Error;
+
+/// The browser's `localStorage`, limited to a few megabytes per origin.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LocalStore;
+
+impl Store for LocalStore {
+    async fn load(&self, key: &str) -> Result<Option<String>, Error> {
+        LocalStorage::raw()
+            .get_item(key)
+            .map_err(|error| Error::Storage(format!("{:?}", error)))
+    }
+
+    async fn save(&self, key: &str, value: String) -> Result<(), Error> {
+        LocalStorage::raw()
+            .set_item(key, &value)
+            .map_err(|error| Error::Storage(format!("{:?}", error)))
+    }
+
+    async fn remove(&self, key: &str) -> Result<(), Error> {
+        LocalStorage::delete(key);
+        Ok(())
+    }
+}
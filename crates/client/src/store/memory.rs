@@ -0,0 +1,50 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use super::Store;
+use crate::error::Error;
+
+/// Keeps everything in memory, shared between clones. Meant for tests.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore(Arc<Mutex<HashMap<String, String>>>);
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Store for MemoryStore {
+    async fn load(&self, key: &str) -> Result<Option<String>, Error> {
+        Ok(self.0.lock().unwrap().get(key).cloned())
+    }
+
+    async fn save(&self, key: &str, value: String) -> Result<(), Error> {
+        self.0.lock().unwrap().insert(key.to_string(), value);
+        Ok(())
+    }
+
+    async fn remove(&self, key: &str) -> Result<(), Error> {
+        self.0.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_store() {
+        let store = MemoryStore::new();
+        assert!(store.load("key").await.unwrap().is_none());
+
+        store.save("key", "value".to_string()).await.unwrap();
+        assert!(store.clone().load("key").await.unwrap() == Some("value".to_string()));
+
+        store.remove("key").await.unwrap();
+        assert!(store.load("key").await.unwrap().is_none());
+    }
+}
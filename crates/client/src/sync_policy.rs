@@ -0,0 +1,31 @@
+use std::time::Duration;
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+/// How much history a source syncs and how often it goes back to the server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncPolicy {
+    /// How far before the last sync each incremental sync looks again, to
+    /// catch updates that landed while the previous one was running.
+    pub backfill: Duration,
+    /// Drop the cache and sync everything again once the last full sync is
+    /// this old. `None` never does.
+    pub full_resync_every: Option<Duration>,
+    /// Merge requests last updated before this are never synced. Changing it
+    /// rebuilds the cache.
+    pub start: DateTime<Utc>,
+    /// How long a sync is served from the cache before asking the server again.
+    pub ttl: Duration,
+}
+
+impl Default for SyncPolicy {
+    fn default() -> Self {
+        Self {
+            backfill: Duration::ZERO,
+            full_resync_every: None,
+            start: Utc.with_ymd_and_hms(2024, 10, 28, 12, 0, 0).unwrap(),
+            ttl: Duration::from_secs(5 * 60),
+        }
+    }
+}
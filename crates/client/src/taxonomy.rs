@@ -0,0 +1,343 @@
+use std::{cmp::Reverse, collections::BTreeMap, fmt};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    change_request::{ChangeRequest, Tag},
+    error::Error,
+};
+
+/// The tags a team agreed on, usually kept in a `taxonomy.toml` next to the
+/// code:
+///
+/// ```toml
+/// version = 2
+///
+/// [categories.security]
+/// description = "Weaknesses an attacker could use"
+/// aliases = ["sec"]
+///
+/// [categories.security.sub_categories.injection]
+/// aliases = ["sqli"]
+///
+/// [categories.oversight]
+/// deprecated = true
+/// replaced_by = "correctness"
+/// ```
+///
+/// Levels below an entry without sub-categories are left free.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Taxonomy {
+    #[serde(default)]
+    pub categories: BTreeMap<String, TaxonomyEntry>,
+    /// Bumped by the team on every change, to tell reports apart.
+    pub version: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaxonomyEntry {
+    /// Other spellings normalized to this entry.
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub deprecated: bool,
+    pub description: Option<String>,
+    /// The tag to use instead of a deprecated entry, e.g. `correctness/logic`.
+    pub replaced_by: Option<String>,
+    #[serde(default)]
+    pub sub_categories: BTreeMap<String, TaxonomyEntry>,
+}
+
+/// Where a tag stands in a [`Taxonomy`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagCheck {
+    /// The tag as spelled in the taxonomy, aliases resolved.
+    Known(Tag),
+    Deprecated {
+        tag: Tag,
+        replaced_by: Option<Tag>,
+    },
+    /// `suggestion` is the closest known tag, when one is close enough to be
+    /// a typo.
+    Unknown {
+        suggestion: Option<Tag>,
+    },
+}
+
+/// A tag out of line with the taxonomy, and the notes using it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagIssue {
+    pub check: TagCheck,
+    pub tag: Tag,
+    pub urls: Vec<String>,
+}
+
+impl Taxonomy {
+    pub fn from_toml(str: &str) -> Result<Self, Error> {
+        toml::from_str(str).map_err(|error| Error::Decode(error.to_string()))
+    }
+
+    pub fn from_json(str: &str) -> Result<Self, Error> {
+        Ok(serde_json::from_str(str)?)
+    }
+
+    /// Reads a `.json` taxonomy, or a TOML one under any other extension.
+    #[cfg(feature = "file")]
+    pub fn read(path: &std::path::Path) -> Result<Self, Error> {
+        let str =
+            std::fs::read_to_string(path).map_err(|error| Error::Storage(error.to_string()))?;
+        match path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            true => Self::from_json(&str),
+            false => Self::from_toml(&str),
+        }
+    }
+
+    pub fn check(&self, tag: &Tag) -> TagCheck {
+        let mut entries = &self.categories;
+        let mut path = vec![];
+        for (depth, level) in tag.path.iter().enumerate() {
+            if depth > 0 && entries.is_empty() {
+                path.extend(tag.path[depth..].iter().cloned());
+                break;
+            }
+            let Some((name, entry)) = entries.iter().find(|(name, entry)| {
+                name.eq_ignore_ascii_case(level)
+                    || entry
+                        .aliases
+                        .iter()
+                        .any(|alias| alias.eq_ignore_ascii_case(level))
+            }) else {
+                let suggestion = entries
+                    .keys()
+                    .map(|name| (distance(&name.to_lowercase(), &level.to_lowercase()), name))
+                    .filter(|(distance, _)| *distance <= 2)
+                    .min()
+                    .map(|(_, name)| Tag {
+                        path: path.iter().chain([name]).cloned().collect(),
+                    });
+                return TagCheck::Unknown { suggestion };
+            };
+            path.push(name.clone());
+            if entry.deprecated {
+                let replaced_by = entry.replaced_by.as_deref().map(|replaced_by| Tag {
+                    path: Tag::from(replaced_by)
+                        .path
+                        .into_iter()
+                        .chain(tag.path[depth + 1..].iter().cloned())
+                        .collect(),
+                });
+                path.extend(tag.path[depth + 1..].iter().cloned());
+                return TagCheck::Deprecated {
+                    tag: Tag { path },
+                    replaced_by,
+                };
+            }
+            entries = &entry.sub_categories;
+        }
+        TagCheck::Known(Tag { path })
+    }
+
+    /// Resolves aliases and replaces deprecated tags, leaving unknown ones
+    /// as they are.
+    pub fn normalize(&self, tags: Vec<Tag>) -> Vec<Tag> {
+        let mut normalized = vec![];
+        for tag in tags {
+            let tag = match self.check(&tag) {
+                TagCheck::Known(tag)
+                | TagCheck::Deprecated {
+                    replaced_by: Some(tag),
+                    ..
+                }
+                | TagCheck::Deprecated { tag, .. } => tag,
+                TagCheck::Unknown { .. } => tag,
+            };
+            if !normalized.contains(&tag) {
+                normalized.push(tag);
+            }
+        }
+        normalized
+    }
+
+    /// Lists the unknown and deprecated tags of `change_requests` as written
+    /// in their notes, the most used first.
+    pub fn validate(&self, change_requests: &[ChangeRequest]) -> Vec<TagIssue> {
+        let mut issues = BTreeMap::<&Tag, TagIssue>::new();
+        for change_request in change_requests {
+            for tag in &change_request.original_tags {
+                let check = self.check(tag);
+                if matches!(check, TagCheck::Known(_)) {
+                    continue;
+                }
+                issues
+                    .entry(tag)
+                    .or_insert_with(|| TagIssue {
+                        check,
+                        tag: tag.clone(),
+                        urls: vec![],
+                    })
+                    .urls
+                    .push(change_request.url.clone());
+            }
+        }
+
+        let mut issues = issues.into_values().collect::<Vec<_>>();
+        issues.sort_by_key(|issue| Reverse(issue.urls.len()));
+        issues
+    }
+}
+
+impl fmt::Display for TagIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.check {
+            TagCheck::Known(_) => write!(f, "#{} is known", self.tag),
+            TagCheck::Deprecated {
+                replaced_by: Some(replaced_by),
+                ..
+            } => write!(f, "#{} is deprecated, use #{}", self.tag, replaced_by),
+            TagCheck::Deprecated { .. } => write!(f, "#{} is deprecated", self.tag),
+            TagCheck::Unknown {
+                suggestion: Some(suggestion),
+            } => write!(f, "#{} is unknown, did you mean #{}?", self.tag, suggestion),
+            TagCheck::Unknown { .. } => write!(f, "#{} is unknown", self.tag),
+        }
+    }
+}
+
+/// The Levenshtein distance between `a` and `b`.
+fn distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::change_request;
+
+    const TAXONOMY: &str = r#"
+        version = 1
+
+        [categories.security]
+        aliases = ["sec"]
+
+        [categories.security.sub_categories.injection]
+        description = "SQL, shell or template injection"
+        aliases = ["sqli"]
+
+        [categories.testing]
+
+        [categories.oversight]
+        deprecated = true
+        replaced_by = "correctness"
+    "#;
+
+    #[test]
+    fn test_check() {
+        let taxonomy = Taxonomy::from_toml(TAXONOMY).unwrap();
+        let check = |tag| taxonomy.check(&Tag::from(tag));
+
+        assert!(check("sec/SQLI") == TagCheck::Known(Tag::from("security/injection")));
+        assert!(check("testing/unit/mocks") == TagCheck::Known(Tag::from("testing/unit/mocks")));
+        assert!(
+            check("oversight/typo")
+                == TagCheck::Deprecated {
+                    tag: Tag::from("oversight/typo"),
+                    replaced_by: Some(Tag::from("correctness/typo")),
+                }
+        );
+        assert!(
+            check("security/injektion")
+                == TagCheck::Unknown {
+                    suggestion: Some(Tag::from("security/injection")),
+                }
+        );
+        assert!(check("style") == TagCheck::Unknown { suggestion: None });
+
+        assert!(
+            taxonomy.normalize(vec![
+                Tag::from("sec/injection"),
+                Tag::from("security/sqli"),
+                Tag::from("oversight"),
+                Tag::from("style"),
+            ]) == vec![
+                Tag::from("security/injection"),
+                Tag::from("correctness"),
+                Tag::from("style"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let taxonomy = Taxonomy::from_toml(TAXONOMY).unwrap();
+        let tagged = |id, tags: &[&str]| ChangeRequest {
+            original_tags: tags.iter().map(|tag| Tag::from(*tag)).collect(),
+            url: format!(
+                "https://gitlab.com/group/project/-/merge_requests/7#note_{}",
+                id
+            ),
+            ..change_request(id, 7, "")
+        };
+        let issues = taxonomy.validate(&[
+            tagged(100, &["security/injektion", "testing"]),
+            tagged(101, &["oversight/typo"]),
+            tagged(102, &["security/injektion"]),
+        ]);
+        assert!(
+            issues
+                .iter()
+                .map(|issue| (issue.to_string(), issue.urls.len()))
+                .collect::<Vec<_>>()
+                == vec![
+                    (
+                        "#security/injektion is unknown, did you mean #security/injection?"
+                            .to_string(),
+                        2
+                    ),
+                    (
+                        "#oversight/typo is deprecated, use #correctness/typo".to_string(),
+                        1
+                    ),
+                ]
+        );
+    }
+
+    #[cfg(feature = "file")]
+    #[test]
+    fn test_read() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("taxonomy.json");
+        std::fs::write(&path, r#"{ "version": 3 }"#).unwrap();
+        assert!(Taxonomy::read(&path).unwrap().version == 3);
+        let path = directory.path().join("taxonomy.toml");
+        std::fs::write(&path, TAXONOMY).unwrap();
+        assert!(Taxonomy::read(&path).unwrap().version == 1);
+        assert!(matches!(
+            Taxonomy::read(&directory.path().join("missing.toml")),
+            Err(Error::Storage(_))
+        ));
+    }
+
+    #[test]
+    fn test_from_json() {
+        let taxonomy =
+            Taxonomy::from_json(r#"{ "version": 1, "categories": { "security": {} } }"#).unwrap();
+        assert!(taxonomy.categories.contains_key("security"));
+        assert!(matches!(
+            Taxonomy::from_toml("categories = 1"),
+            Err(Error::Decode(_))
+        ));
+    }
+}
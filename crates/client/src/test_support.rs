@@ -0,0 +1,123 @@
+//! Fixtures shared by the unit tests of the crate.
+
+use chrono::DateTime;
+use serde_json::json;
+use wiremock::{
+    matchers::{method, path, path_regex},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use crate::change_request::{ChangeRequest, MergeRequestMetadata, NoteKind};
+
+/// A change request on `group/project` with nothing but its ids and text set.
+pub fn change_request(id: u64, merge_request_id: u64, description: &str) -> ChangeRequest {
+    ChangeRequest {
+        author: "author".to_string(),
+        author_replied: false,
+        conventional_comment: None,
+        created_at: DateTime::UNIX_EPOCH,
+        description: description.to_string(),
+        id,
+        kind: NoteKind::Note,
+        merge_request: MergeRequestMetadata::default(),
+        merge_request_created_at: DateTime::UNIX_EPOCH,
+        merge_request_id,
+        merge_request_merged_at: None,
+        original_tags: vec![],
+        position: None,
+        project: "group/project".to_string(),
+        reactions: vec![],
+        replies: 0,
+        resolvable: false,
+        resolved: false,
+        resolved_at: None,
+        resolved_by: None,
+        reviewer: "reviewer".to_string(),
+        severity: None,
+        suggestions: vec![],
+        tags: vec![],
+        updated_at: DateTime::UNIX_EPOCH,
+        url: String::new(),
+    }
+}
+
+/// A note as returned by the GitLab REST API.
+pub fn note(id: u64, author_id: u64, body: &str) -> serde_json::Value {
+    json!({
+        "author": { "id": author_id, "username": format!("user-{}", author_id) },
+        "body": body,
+        "created_at": "2024-11-01T09:00:00Z",
+        "id": id,
+        "resolvable": false,
+        "resolved": false,
+        "resolved_at": null,
+        "resolved_by": null,
+        "system": false,
+        "updated_at": "2024-11-01T10:00:00Z",
+    })
+}
+
+/// A merge request of `group/project`, project 42, as returned by the GitLab
+/// REST API.
+pub fn merge_request(iid: u64, author_id: u64) -> serde_json::Value {
+    json!({
+        "author": { "id": author_id, "username": format!("user-{}", author_id) },
+        "created_at": "2024-10-30T10:00:00Z",
+        "draft": false,
+        "iid": iid,
+        "labels": ["bug"],
+        "merged_at": null,
+        "project_id": 42,
+        "references": { "full": format!("group/project!{}", iid) },
+        "state": "opened",
+        "target_branch": "main",
+        "title": "Fix the parser",
+        "web_url": format!("https://gitlab.com/group/project/-/merge_requests/{}", iid),
+    })
+}
+
+/// Serves merge request 7 of `group/project` with `discussions`.
+pub async fn mount(server: &MockServer, discussions: serde_json::Value) {
+    mount_merge_requests(server, vec![(merge_request(7, 1), discussions)]).await;
+}
+
+/// Serves the merge requests of `group/project`, each with its discussions, a
+/// one-file diff adding two lines and removing one, and no award emoji.
+pub async fn mount_merge_requests(
+    server: &MockServer,
+    merge_requests: Vec<(serde_json::Value, serde_json::Value)>,
+) {
+    server.reset().await;
+    let list = merge_requests
+        .iter()
+        .map(|(merge_request, _)| merge_request.clone())
+        .collect::<Vec<_>>();
+    Mock::given(method("GET"))
+        .and(path("/api/v4/projects/group%2Fproject/merge_requests"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(list))
+        .mount(server)
+        .await;
+    for (merge_request, discussions) in merge_requests {
+        let iid = &merge_request["iid"];
+        Mock::given(method("GET"))
+            .and(path(format!("/api/v4/projects/42/merge_requests/{}", iid)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&merge_request))
+            .mount(server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/api/v4/projects/42/merge_requests/{}/discussions",
+                iid
+            )))
+            .respond_with(ResponseTemplate::new(200).set_body_json(discussions))
+            .mount(server)
+            .await;
+    }
+    Mock::given(method("GET"))
+        .and(path_regex(
+            r"^/api/v4/projects/\d+/merge_requests/\d+/notes/\d+/award_emoji$",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+        .mount(server)
+        .await;
+}
@@ -0,0 +1,550 @@
+use std::{convert::Infallible, pin::pin, sync::Arc, time::Duration};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use futures::{
+    channel::mpsc,
+    future::{select, Either},
+    stream::FuturesUnordered,
+    StreamExt,
+};
+use http_body_util::{BodyExt, Full, LengthLimitError, Limited};
+use hyper::{
+    body::{Bytes, Incoming},
+    server::conn::http1,
+    service::service_fn,
+    Method, Request, Response, StatusCode,
+};
+use hyper_util::rt::TokioIo;
+use log::*;
+use serde::{Deserialize, Deserializer};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+
+use crate::{
+    change_request::{ChangeRequest, DiffPosition, Reaction},
+    error::Error,
+    gitlab_client::{GitlabClient, NotePosition},
+    store::Store,
+};
+
+/// Well above the size of note and merge request deliveries, which are read
+/// whole before being decoded.
+const MAX_BODY_SIZE: usize = 2 * 1024 * 1024;
+
+/// How long a client has to send its delivery.
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many deliveries can wait to be applied before new ones are refused.
+const QUEUE_SIZE: usize = 100;
+
+/// Receives GitLab "Note Hook", "Merge Request Hook" and "Emoji Hook"
+/// deliveries and folds them into the cache of a [`GitlabClient`], as an
+/// alternative to polling.
+///
+/// Edits and reactions to notes already in the cache are applied from the
+/// payload alone.
+/// Anything else, a new thread, a reply or a change to the merge request,
+/// resyncs that single merge request through the API.
+#[derive(Clone)]
+pub struct WebhookReceiver<S> {
+    client: GitlabClient<S>,
+    on_change: Option<Arc<dyn Fn(Vec<ChangeRequest>) + Send + Sync>>,
+    secret: String,
+}
+
+impl<S: Store> WebhookReceiver<S> {
+    /// `secret` is the token configured on the GitLab webhook, sent back in
+    /// the `X-Gitlab-Token` header of every delivery.
+    pub fn new(client: GitlabClient<S>, secret: String) -> Self {
+        Self {
+            client,
+            on_change: None,
+            secret,
+        }
+    }
+
+    /// Called with the change requests touched by each delivery.
+    pub fn on_change(
+        mut self,
+        on_change: impl Fn(Vec<ChangeRequest>) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_change = Some(Arc::new(on_change));
+        self
+    }
+
+    /// Handles one delivery, for embedding the receiver in another server.
+    /// Fails with [`Error::Auth`] when `token` is not the secret and with
+    /// [`Error::Decode`] when the payload cannot be read.
+    pub async fn handle(
+        &self,
+        token: Option<&str>,
+        body: &[u8],
+    ) -> Result<Vec<ChangeRequest>, Error> {
+        if !self.authorized(token) {
+            return Err(Error::Auth);
+        }
+        self.apply(serde_json::from_slice(body)?).await
+    }
+
+    fn authorized(&self, token: Option<&str>) -> bool {
+        token.is_some_and(|token| constant_time_eq(token.as_bytes(), self.secret.as_bytes()))
+    }
+
+    /// Folds one event into the cache, returning the change requests it
+    /// touched.
+    async fn apply(&self, event: Event) -> Result<Vec<ChangeRequest>, Error> {
+        let change_requests = match event {
+            Event::Note {
+                merge_request: Some(merge_request),
+                object_attributes: note,
+                project,
+            } if note.noteable_type == "MergeRequest" && !note.system => {
+                self.on_note(*note, merge_request.iid, project).await?
+            }
+            Event::Emoji {
+                event_type,
+                object_attributes: emoji,
+                project,
+                user,
+            } if emoji.awardable_type == "Note" => {
+                let reaction = Reaction {
+                    name: emoji.name,
+                    user: user.username,
+                };
+                self.client
+                    .update_cached(
+                        &project.path_with_namespace,
+                        project.id,
+                        emoji.awardable_id,
+                        |change_request| {
+                            change_request.reactions.retain(|value| *value != reaction);
+                            if event_type == "award" && !change_request.tags.is_empty() {
+                                change_request.reactions.push(reaction);
+                            }
+                        },
+                    )
+                    .await?
+                    .into_iter()
+                    .collect()
+            }
+            Event::MergeRequest {
+                object_attributes: merge_request,
+                project,
+            } => {
+                self.client
+                    .sync_merge_request(project.id, merge_request.iid)
+                    .await?
+            }
+            _ => vec![],
+        };
+
+        if let Some(on_change) = &self.on_change {
+            if !change_requests.is_empty() {
+                on_change(change_requests.clone());
+            }
+        }
+        Ok(change_requests)
+    }
+
+    async fn on_note(
+        &self,
+        note: HookNote,
+        iid: u64,
+        project: HookProject,
+    ) -> Result<Vec<ChangeRequest>, Error> {
+        let mut parsed_note = self.client.parse_note(note.note);
+        let position = note.position.map(DiffPosition::from);
+        let updated = self
+            .client
+            .update_cached(
+                &project.path_with_namespace,
+                project.id,
+                note.id,
+                |change_request| {
+                    // Hooks do not say whether suggestions were applied,
+                    // editing a note keeps what was known.
+                    for (suggestion, cached) in parsed_note
+                        .suggestions
+                        .iter_mut()
+                        .zip(&change_request.suggestions)
+                    {
+                        suggestion.applied = cached.applied;
+                    }
+                    change_request.conventional_comment = parsed_note.conventional_comment;
+                    change_request.description = parsed_note.description;
+                    change_request.original_tags = parsed_note.original_tags;
+                    change_request.position = position.or(change_request.position.take());
+                    if parsed_note.tags.is_empty() {
+                        change_request.reactions.clear();
+                    }
+                    change_request.severity = parsed_note.severity;
+                    change_request.suggestions = parsed_note.suggestions;
+                    change_request.tags = parsed_note.tags;
+                    change_request.updated_at = note.updated_at;
+                },
+            )
+            .await?;
+
+        match updated {
+            Some(change_request) => Ok(vec![change_request]),
+            None => self.client.sync_merge_request(project.id, iid).await,
+        }
+    }
+
+    /// Answers deliveries on `listener` until it fails. Deliveries are
+    /// acknowledged as soon as they are read and applied one at a time in the
+    /// background, so that GitLab is not kept waiting on resyncs.
+    pub async fn serve(&self, listener: TcpListener) -> std::io::Result<()> {
+        info!("Listening for webhooks on {}", listener.local_addr()?);
+        let (sender, mut receiver) = mpsc::channel(QUEUE_SIZE);
+
+        let apply = async move {
+            while let Some(event) = receiver.next().await {
+                if let Err(error) = self.apply(event).await {
+                    warn!("Failed to handle webhook: {}", error);
+                }
+            }
+        };
+        let accept = async move {
+            let mut connections = FuturesUnordered::new();
+            loop {
+                let accepted = match connections.is_empty() {
+                    true => listener.accept().await,
+                    // Accepting is cancel safe, so it can be restarted
+                    // whenever a connection is done.
+                    false => match select(pin!(listener.accept()), connections.next()).await {
+                        Either::Left((accepted, _)) => accepted,
+                        Either::Right(_) => continue,
+                    },
+                };
+                let (stream, _) = accepted?;
+                connections.push(self.connection(stream, sender.clone()));
+            }
+        };
+
+        match select(pin!(apply), pin!(accept)).await {
+            Either::Left(((), _)) => Ok(()),
+            Either::Right((result, _)) => result,
+        }
+    }
+
+    /// Serves the single request of a connection, within [`CONNECTION_TIMEOUT`].
+    async fn connection(&self, stream: TcpStream, sender: mpsc::Sender<Event>) {
+        let service = service_fn(|request| self.respond(request, sender.clone()));
+        let connection = http1::Builder::new()
+            .keep_alive(false)
+            .serve_connection(TokioIo::new(stream), service);
+        match timeout(CONNECTION_TIMEOUT, connection).await {
+            Ok(Ok(())) => {}
+            Ok(Err(error)) => warn!("Webhook connection failed: {}", error),
+            Err(_) => warn!("Webhook connection timed out"),
+        }
+    }
+
+    /// Checks the secret before reading anything else, then queues the event.
+    async fn respond(
+        &self,
+        request: Request<Incoming>,
+        mut sender: mpsc::Sender<Event>,
+    ) -> Result<Response<Full<Bytes>>, Infallible> {
+        if request.method() != Method::POST {
+            return Ok(status(StatusCode::METHOD_NOT_ALLOWED));
+        }
+        let token = request
+            .headers()
+            .get("x-gitlab-token")
+            .and_then(|value| value.to_str().ok());
+        if !self.authorized(token) {
+            return Ok(status(StatusCode::UNAUTHORIZED));
+        }
+        let body = match Limited::new(request.into_body(), MAX_BODY_SIZE)
+            .collect()
+            .await
+        {
+            Ok(body) => body.to_bytes(),
+            Err(error) if error.is::<LengthLimitError>() => {
+                return Ok(status(StatusCode::PAYLOAD_TOO_LARGE))
+            }
+            Err(_) => return Ok(status(StatusCode::BAD_REQUEST)),
+        };
+        let event = match serde_json::from_slice(&body) {
+            Ok(event) => event,
+            Err(error) => {
+                warn!("Unreadable webhook payload: {}", error);
+                return Ok(status(StatusCode::BAD_REQUEST));
+            }
+        };
+
+        Ok(status(match sender.try_send(event) {
+            Ok(()) => StatusCode::OK,
+            Err(_) => {
+                warn!("Too many webhooks queued, dropping one");
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+        }))
+    }
+}
+
+fn status(status: StatusCode) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::default());
+    *response.status_mut() = status;
+    response
+}
+
+/// Compares secrets without leaking how much of them matched.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "object_kind", rename_all = "snake_case")]
+enum Event {
+    Note {
+        merge_request: Option<HookMergeRequest>,
+        object_attributes: Box<HookNote>,
+        project: HookProject,
+    },
+    MergeRequest {
+        object_attributes: HookMergeRequest,
+        project: HookProject,
+    },
+    /// `event_type` is `award` or `revoke`.
+    Emoji {
+        event_type: String,
+        object_attributes: HookEmoji,
+        project: HookProject,
+        user: HookUser,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct HookMergeRequest {
+    pub iid: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct HookNote {
+    pub id: u64,
+    pub note: String,
+    pub noteable_type: String,
+    #[serde(default)]
+    pub position: Option<NotePosition>,
+    #[serde(default)]
+    pub system: bool,
+    #[serde(deserialize_with = "timestamp")]
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HookEmoji {
+    pub awardable_id: u64,
+    pub awardable_type: String,
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct HookUser {
+    pub username: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct HookProject {
+    pub id: u64,
+    pub path_with_namespace: String,
+}
+
+/// Hooks date their notes as `2024-11-01 10:00:00 UTC` rather than RFC 3339.
+fn timestamp<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    value
+        .parse::<DateTime<Utc>>()
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(&value, "%Y-%m-%d %H:%M:%S UTC")
+                .map(|value| value.and_utc())
+        })
+        .map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use wiremock::MockServer;
+
+    use super::*;
+    use crate::{
+        gitlab_client::Scope,
+        review_source::ReviewSource,
+        reviewers::Reviewers,
+        store::MemoryStore,
+        test_support::{mount, note},
+    };
+
+    fn note_hook(id: u64, body: &str) -> Vec<u8> {
+        json!({
+            "object_kind": "note",
+            "merge_request": { "iid": 7 },
+            "object_attributes": {
+                "id": id,
+                "note": body,
+                "noteable_type": "MergeRequest",
+                "system": false,
+                "updated_at": "2024-11-02 10:00:00 UTC",
+            },
+            "project": { "id": 42, "path_with_namespace": "group/project" },
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    fn emoji_hook(event_type: &str, note_id: u64, name: &str) -> Vec<u8> {
+        json!({
+            "object_kind": "emoji",
+            "event_type": event_type,
+            "object_attributes": {
+                "awardable_id": note_id,
+                "awardable_type": "Note",
+                "id": 1,
+                "name": name,
+                "user_id": 3,
+            },
+            "project": { "id": 42, "path_with_namespace": "group/project" },
+            "user": { "id": 3, "username": "user-3" },
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    async fn receiver(server: &MockServer) -> WebhookReceiver<MemoryStore> {
+        let client = GitlabClient::new(
+            server.uri(),
+            "token".to_string(),
+            Scope::Projects(vec!["group/project".to_string()]),
+            Reviewers::NonAuthors,
+            MemoryStore::new(),
+        );
+        client.fetch().await.unwrap();
+        WebhookReceiver::new(client, "secret".to_string())
+    }
+
+    #[tokio::test]
+    async fn test_handle() {
+        let server = MockServer::start().await;
+        mount(&server, json!([{ "notes": [note(100, 2, "first")] }])).await;
+        let receiver = receiver(&server).await;
+
+        let result = receiver.handle(Some("wrong"), &note_hook(100, "")).await;
+        assert!(result == Err(Error::Auth));
+        let result = receiver.handle(Some("secret"), b"{").await;
+        assert!(matches!(result, Err(Error::Decode(_))));
+
+        // Known notes are updated from the payload, without asking GitLab.
+        server.reset().await;
+        let change_requests = receiver
+            .handle(Some("secret"), &note_hook(100, "first\n#testing/unit"))
+            .await
+            .unwrap();
+        assert!(change_requests.len() == 1);
+        assert!(change_requests[0].tags[0].category() == "testing");
+        assert!(
+            change_requests[0].updated_at
+                == "2024-11-02T10:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+
+        // So are reactions.
+        let change_requests = receiver
+            .handle(Some("secret"), &emoji_hook("award", 100, "thumbsdown"))
+            .await
+            .unwrap();
+        assert!(
+            change_requests[0].reactions
+                == vec![Reaction {
+                    name: "thumbsdown".to_string(),
+                    user: "user-3".to_string(),
+                }]
+        );
+        let change_requests = receiver
+            .handle(Some("secret"), &emoji_hook("revoke", 100, "thumbsdown"))
+            .await
+            .unwrap();
+        assert!(change_requests[0].reactions.is_empty());
+
+        // New threads resync the merge request.
+        mount(
+            &server,
+            json!([
+                { "notes": [note(100, 2, "first\n#testing/unit")] },
+                { "notes": [note(200, 2, "second")] },
+            ]),
+        )
+        .await;
+        let change_requests = receiver
+            .handle(Some("secret"), &note_hook(200, "second"))
+            .await
+            .unwrap();
+        assert!(
+            change_requests
+                .iter()
+                .map(|value| value.id)
+                .collect::<Vec<_>>()
+                == vec![100, 200]
+        );
+
+        let cached = receiver.client.fetch().await.unwrap().change_requests;
+        assert!(cached == change_requests);
+    }
+
+    #[tokio::test]
+    async fn test_serve() {
+        let server = MockServer::start().await;
+        mount(&server, json!([{ "notes": [note(100, 2, "first")] }])).await;
+        let (sender, mut changes) = mpsc::unbounded();
+        let receiver = receiver(&server).await.on_change(move |change_requests| {
+            sender.unbounded_send(change_requests).unwrap();
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let requests = async {
+            let client = reqwest::Client::new();
+            let post = |token: &str, body: Vec<u8>| {
+                client
+                    .post(&url)
+                    .header("x-gitlab-token", token)
+                    .body(body)
+                    .send()
+            };
+            let statuses = [
+                post("wrong", note_hook(100, "")).await.unwrap().status(),
+                post("secret", vec![b' '; MAX_BODY_SIZE + 1])
+                    .await
+                    .unwrap()
+                    .status(),
+                post("secret", b"{".to_vec()).await.unwrap().status(),
+                post("secret", note_hook(100, "edited"))
+                    .await
+                    .unwrap()
+                    .status(),
+            ];
+            // Applied after the delivery was acknowledged.
+            (statuses, changes.next().await.unwrap())
+        };
+        let (statuses, changed) = tokio::select! {
+            done = requests => done,
+            result = receiver.serve(listener) => panic!("server stopped: {:?}", result),
+        };
+        assert!(
+            statuses
+                == [
+                    StatusCode::UNAUTHORIZED,
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    StatusCode::BAD_REQUEST,
+                    StatusCode::OK,
+                ]
+        );
+        assert!(changed.len() == 1 && changed[0].description == "edited");
+    }
+}
@@ -0,0 +1,184 @@
+//! A stand-in for the GitLab REST API, serving the responses recorded in
+//! `tests/fixtures/gitlab` over loopback.
+
+use std::{fs, path::Path};
+
+use client::{store::MemoryStore, GitlabClient, Reviewers, Scheduler, Scope};
+use serde_json::Value;
+use wiremock::{
+    matchers::{any, header, method, path, path_regex, query_param, query_param_is_missing},
+    Mock, MockServer, ResponseTemplate,
+};
+
+pub const TOKEN: &str = "glpat-fixture";
+pub const PROJECT: &str = "group/project";
+
+const MERGE_REQUESTS: &str = "/api/v4/projects/group%2Fproject/merge_requests";
+
+pub fn fixture(name: &str) -> Value {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/gitlab")
+        .join(format!("{}.json", name));
+    let json = fs::read_to_string(&path).unwrap_or_else(|_| panic!("missing {:?}", path));
+    serde_json::from_str(&json).unwrap()
+}
+
+pub fn discussions(iid: u64) -> String {
+    format!("/api/v4/projects/42/merge_requests/{}/discussions", iid)
+}
+
+pub fn note(iid: u64, id: u64) -> String {
+    format!("{}/{}/notes/{}", MERGE_REQUESTS, iid, id)
+}
+
+pub struct MockGitlab {
+    pub server: MockServer,
+}
+
+impl MockGitlab {
+    /// Serves project `group/project`: merge requests 7 and 8 on the first
+    /// page and 9 on the second, the discussions of 7 over two pages and
+    /// reactions on note 100.
+    pub async fn start() -> Self {
+        let gitlab = Self {
+            server: MockServer::start().await,
+        };
+
+        let next_page = |url: String| {
+            ResponseTemplate::new(200).insert_header("link", format!(r#"<{}>; rel="next""#, url))
+        };
+        gitlab
+            .mount(
+                Mock::given(method("GET"))
+                    .and(path(MERGE_REQUESTS))
+                    .and(query_param_is_missing("page")),
+                ResponseTemplate::new(200).insert_header("x-next-page", "2"),
+                "merge_requests_1",
+            )
+            .await;
+        gitlab
+            .mount(
+                Mock::given(method("GET"))
+                    .and(path(MERGE_REQUESTS))
+                    .and(query_param("page", "2")),
+                ResponseTemplate::new(200).insert_header("x-next-page", ""),
+                "merge_requests_2",
+            )
+            .await;
+        gitlab
+            .mount(
+                Mock::given(method("GET"))
+                    .and(path(discussions(7)))
+                    .and(query_param_is_missing("page")),
+                next_page(format!(
+                    "{}{}?page=2&per_page=100",
+                    gitlab.server.uri(),
+                    discussions(7)
+                )),
+                "discussions_7_1",
+            )
+            .await;
+        gitlab
+            .mount(
+                Mock::given(method("GET"))
+                    .and(path(discussions(7)))
+                    .and(query_param("page", "2")),
+                ResponseTemplate::new(200),
+                "discussions_7_2",
+            )
+            .await;
+        for iid in [8, 9] {
+            gitlab
+                .mount(
+                    Mock::given(method("GET")).and(path(discussions(iid))),
+                    ResponseTemplate::new(200),
+                    &format!("discussions_{}", iid),
+                )
+                .await;
+        }
+        gitlab
+            .mount(
+                Mock::given(method("GET")).and(path(
+                    "/api/v4/projects/42/merge_requests/7/notes/100/award_emoji",
+                )),
+                ResponseTemplate::new(200),
+                "award_emoji_100",
+            )
+            .await;
+        gitlab
+            .mount(
+                Mock::given(method("GET")).and(path_regex(r"/notes/\d+/award_emoji$")),
+                ResponseTemplate::new(200),
+                "award_emoji",
+            )
+            .await;
+        gitlab
+            .mount(
+                Mock::given(method("GET")).and(path(note(8, 200))),
+                ResponseTemplate::new(200),
+                "note_200",
+            )
+            .await;
+        // Like GitLab: unknown routes are not found, unknown tokens are not let in.
+        Mock::given(header("private-token", TOKEN))
+            .respond_with(
+                ResponseTemplate::new(404)
+                    .set_body_json(serde_json::json!({ "message": "404 Not Found" })),
+            )
+            .with_priority(u8::MAX - 1)
+            .mount(&gitlab.server)
+            .await;
+        Mock::given(any())
+            .respond_with(
+                ResponseTemplate::new(401)
+                    .set_body_json(serde_json::json!({ "message": "401 Unauthorized" })),
+            )
+            .with_priority(u8::MAX)
+            .mount(&gitlab.server)
+            .await;
+
+        gitlab
+    }
+
+    /// Answers requests matching `mock` with the `fixture` body, for callers
+    /// with the right token.
+    pub async fn mount(
+        &self,
+        mock: wiremock::MockBuilder,
+        response: ResponseTemplate,
+        fixture: &str,
+    ) {
+        mock.and(header("private-token", TOKEN))
+            .respond_with(response.set_body_json(self::fixture(fixture)))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Answers requests matching `mock` with `response`, taking precedence over
+    /// the fixtures.
+    pub async fn respond(&self, mock: wiremock::MockBuilder, response: ResponseTemplate) {
+        mock.respond_with(response)
+            .with_priority(1)
+            .mount(&self.server)
+            .await;
+    }
+
+    pub fn client(&self, reviewers: Reviewers) -> GitlabClient<MemoryStore> {
+        self.client_with_token(TOKEN, reviewers)
+    }
+
+    pub fn client_with_token(
+        &self,
+        token: &str,
+        reviewers: Reviewers,
+    ) -> GitlabClient<MemoryStore> {
+        GitlabClient::new(
+            self.server.uri(),
+            token.to_string(),
+            Scope::Projects(vec![PROJECT.to_string()]),
+            reviewers,
+            MemoryStore::new(),
+        )
+        .with_scheduler(Scheduler::default().max_retries(0))
+    }
+}
@@ -0,0 +1,346 @@
+mod common;
+
+use std::{fs, path::Path, time::Duration};
+
+use client::{
+    ChangeRequest, CommentLabel, Error, MergeRequestState, ReactionStats, ReviewSource, ReviewerId,
+    Reviewers, SaveOutcome, Severity, SuggestionStats, SyncPolicy, Tag, TagCheck, Taxonomy,
+};
+use common::{discussions, fixture, note, MockGitlab};
+use serde_json::json;
+use wiremock::{
+    matchers::{body_json, method, path},
+    Mock, ResponseTemplate,
+};
+
+fn ids(change_requests: &[ChangeRequest]) -> Vec<u64> {
+    let mut ids = change_requests
+        .iter()
+        .map(|value| value.id)
+        .collect::<Vec<_>>();
+    ids.sort();
+    ids
+}
+
+fn find(change_requests: &[ChangeRequest], id: u64) -> &ChangeRequest {
+    change_requests.iter().find(|value| value.id == id).unwrap()
+}
+
+#[tokio::test]
+async fn test_follows_pagination() {
+    let gitlab = MockGitlab::start().await;
+
+    let report = gitlab.client(Reviewers::NonAuthors).fetch().await.unwrap();
+    assert!(report.errors.is_empty());
+    // 130 is on the second page of the discussions of !7, !9 on the second
+    // page of merge requests.
+    assert!(ids(&report.change_requests) == vec![100, 130, 200, 210, 300]);
+
+    let change_request = find(&report.change_requests, 100);
+    assert!(change_request.project == "group/project");
+    assert!(change_request.merge_request_id == 7);
+    assert!(change_request.tags[0].to_string() == "naming/variable");
+    assert!(change_request.replies == 1);
+    assert!(change_request.author_replied);
+    assert!(change_request.resolved_by.as_deref() == Some("user-1"));
+    assert!(
+        change_request.url
+            == "https://gitlab.example.com/group/project/-/merge_requests/7/#note_100"
+    );
+    let position = change_request.position.as_ref().unwrap();
+    assert!(position.new_path == "src/lib.rs");
+    assert!(position.line_start == Some(18));
+}
+
+#[tokio::test]
+async fn test_reads_conventional_comments() {
+    let gitlab = MockGitlab::start().await;
+
+    let report = gitlab.client(Reviewers::NonAuthors).fetch().await.unwrap();
+    let change_request = find(&report.change_requests, 130);
+    let comment = change_request.conventional_comment.as_ref().unwrap();
+    assert!(comment.label == CommentLabel::Nitpick && comment.subject == "spacing");
+    assert!(change_request.severity == Some(Severity::Minor));
+    assert!(find(&report.change_requests, 200)
+        .conventional_comment
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_reads_suggestions() {
+    let gitlab = MockGitlab::start().await;
+
+    let report = gitlab.client(Reviewers::NonAuthors).fetch().await.unwrap();
+    let suggestions = &find(&report.change_requests, 100).suggestions;
+    assert!(
+        suggestions
+            .iter()
+            .map(|value| (value.lines_above, value.lines_below, value.applied))
+            .collect::<Vec<_>>()
+            == vec![(0, 1, None), (0, 0, None)]
+    );
+    assert!(suggestions[0].code == "    pending: usize,\n    max_retries: u32,");
+
+    // GitLab does not tell whether suggestions were applied.
+    assert!(SuggestionStats::by_category(&report.change_requests).is_empty());
+}
+
+#[tokio::test]
+async fn test_reads_reactions() {
+    let gitlab = MockGitlab::start().await;
+
+    let report = gitlab.client(Reviewers::NonAuthors).fetch().await.unwrap();
+    let change_request = find(&report.change_requests, 100);
+    assert!(change_request.reactions.len() == 4);
+    assert!(
+        ReactionStats::of(change_request)
+            == ReactionStats {
+                agreed: 1,
+                disputed: 2,
+            }
+    );
+    // Only categorized notes have their reactions fetched.
+    assert!(find(&report.change_requests, 130).reactions.is_empty());
+
+    let stats = ReactionStats::by_tag(&report.change_requests);
+    assert!(stats[0].0 == "naming/variable" && stats[0].1.disputed == 2);
+}
+
+#[tokio::test]
+async fn test_attaches_merge_request_metadata() {
+    let gitlab = MockGitlab::start().await;
+
+    let report = gitlab.client(Reviewers::NonAuthors).fetch().await.unwrap();
+    let merge_request = &find(&report.change_requests, 100).merge_request;
+    assert!(merge_request.title == "Add the sync scheduler");
+    assert!(merge_request.labels == vec!["backend", "scheduler"]);
+    assert!(merge_request.target_branch == "main");
+    assert!(merge_request.state == MergeRequestState::Opened && !merge_request.draft);
+    // REST does not report the size of merge requests.
+    assert!(merge_request.diff_stats.is_none());
+
+    let merge_request = &find(&report.change_requests, 200).merge_request;
+    assert!(merge_request.state == MergeRequestState::Merged);
+}
+
+#[tokio::test]
+async fn test_normalizes_tags_to_the_taxonomy() {
+    let gitlab = MockGitlab::start().await;
+    let taxonomy = Taxonomy::from_toml(
+        &fs::read_to_string(
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/taxonomy.toml"),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+
+    let report = gitlab.client(Reviewers::NonAuthors).fetch().await.unwrap();
+    let issues = taxonomy.validate(&report.change_requests);
+    assert!(issues.len() == 1);
+    assert!(issues[0].tag == Tag::from("naming/variable"));
+    assert!(
+        issues[0].check
+            == TagCheck::Deprecated {
+                tag: Tag::from("naming/variable"),
+                replaced_by: Some(Tag::from("readability/naming/variable")),
+            }
+    );
+    assert!(
+        issues[0].urls
+            == vec!["https://gitlab.example.com/group/project/-/merge_requests/7/#note_100"]
+    );
+
+    let report = gitlab
+        .client(Reviewers::NonAuthors)
+        .with_taxonomy(taxonomy.clone())
+        .fetch()
+        .await
+        .unwrap();
+    assert!(
+        find(&report.change_requests, 100).tags == vec![Tag::from("readability/naming/variable")]
+    );
+    // Normalizing does not hide the notes still using the deprecated tag.
+    assert!(taxonomy.validate(&report.change_requests) == issues);
+}
+
+#[tokio::test]
+async fn test_filters_by_reviewer() {
+    let gitlab = MockGitlab::start().await;
+    let fetch = |reviewers| async {
+        let report = gitlab.client(reviewers).fetch().await.unwrap();
+        ids(&report.change_requests)
+    };
+
+    assert!(
+        fetch(Reviewers::Only(vec![ReviewerId::Username(
+            "user-2".to_string()
+        )]))
+        .await
+            == vec![100, 200, 310]
+    );
+    assert!(fetch(Reviewers::Only(vec![ReviewerId::Id(4)])).await == vec![130, 210]);
+    assert!(
+        fetch(Reviewers::Only(vec![
+            ReviewerId::Id(3),
+            ReviewerId::Username("user-4".to_string()),
+        ]))
+        .await
+            == vec![130, 210, 300]
+    );
+    // Unlike listed reviewers, any reviewer leaves out the notes of authors
+    // on their own merge requests.
+    assert!(!fetch(Reviewers::NonAuthors).await.contains(&310));
+}
+
+#[tokio::test]
+async fn test_serves_the_cache() {
+    let gitlab = MockGitlab::start().await;
+    let client = gitlab.client(Reviewers::NonAuthors);
+
+    let fetched = client.fetch().await.unwrap();
+    gitlab.server.reset().await;
+    let cached = client.fetch().await.unwrap();
+    assert!(cached == fetched);
+}
+
+#[tokio::test]
+async fn test_merges_updates_into_the_cache() {
+    let gitlab = MockGitlab::start().await;
+    let client = gitlab
+        .client(Reviewers::NonAuthors)
+        .with_sync_policy(SyncPolicy {
+            ttl: Duration::ZERO,
+            ..SyncPolicy::default()
+        });
+    client.fetch().await.unwrap();
+
+    // Only !8 changed since the last sync: a thread was resolved and another
+    // one started.
+    gitlab
+        .respond(
+            Mock::given(method("GET")).and(path("/api/v4/projects/group%2Fproject/merge_requests")),
+            ResponseTemplate::new(200).set_body_json(fixture("merge_requests_updated")),
+        )
+        .await;
+    gitlab
+        .respond(
+            Mock::given(method("GET")).and(path(discussions(8))),
+            ResponseTemplate::new(200).set_body_json(fixture("discussions_8_updated")),
+        )
+        .await;
+
+    let report = client.fetch().await.unwrap();
+    assert!(report.errors.is_empty());
+    assert!(ids(&report.change_requests) == vec![100, 130, 200, 210, 220, 300]);
+
+    let change_request = find(&report.change_requests, 200);
+    assert!(change_request.resolved);
+    assert!(change_request.resolved_by.as_deref() == Some("user-3"));
+    assert!(change_request.replies == 2);
+    assert!(change_request.author_replied);
+    assert!(find(&report.change_requests, 220).severity == Some(Severity::Info));
+}
+
+#[tokio::test]
+async fn test_rejects_unknown_tokens() {
+    let gitlab = MockGitlab::start().await;
+
+    let result = gitlab
+        .client_with_token("glpat-revoked", Reviewers::NonAuthors)
+        .fetch()
+        .await;
+    assert!(result == Err(Error::Auth));
+}
+
+#[tokio::test]
+async fn test_reports_failing_merge_requests() {
+    let gitlab = MockGitlab::start().await;
+    gitlab
+        .respond(
+            Mock::given(method("GET")).and(path(discussions(8))),
+            ResponseTemplate::new(502),
+        )
+        .await;
+
+    let report = gitlab.client(Reviewers::NonAuthors).fetch().await.unwrap();
+    assert!(ids(&report.change_requests) == vec![100, 130, 300]);
+    assert!(report.errors.len() == 1);
+    assert!(matches!(&report.errors[0], Error::Network(message) if message.contains("502")));
+}
+
+#[tokio::test]
+async fn test_fails_on_unexpected_responses() {
+    let gitlab = MockGitlab::start().await;
+    gitlab
+        .respond(
+            Mock::given(method("GET")).and(path("/api/v4/projects/group%2Fproject/merge_requests")),
+            ResponseTemplate::new(200).set_body_string("<html>Maintenance</html>"),
+        )
+        .await;
+
+    let result = gitlab.client(Reviewers::NonAuthors).fetch().await;
+    assert!(matches!(result, Err(Error::Decode(_))));
+
+    let gitlab = MockGitlab::start().await;
+    gitlab
+        .respond(
+            Mock::given(method("GET")).and(path("/api/v4/projects/group%2Fproject/merge_requests")),
+            ResponseTemplate::new(404).set_body_json(json!({ "message": "404 Project Not Found" })),
+        )
+        .await;
+    let result = gitlab.client(Reviewers::NonAuthors).fetch().await;
+    assert!(matches!(result, Err(Error::Network(message)) if message.contains("404")));
+}
+
+#[tokio::test]
+async fn test_writes_back_categories() {
+    let gitlab = MockGitlab::start().await;
+    let client = gitlab.client(Reviewers::NonAuthors);
+    let report = client.fetch().await.unwrap();
+    let fetched = find(&report.change_requests, 200).clone();
+    assert!(fetched.tags.is_empty());
+
+    let change_request = ChangeRequest {
+        tags: vec![Tag::from("testing/integration"), Tag::from("security/auth")],
+        ..fetched.clone()
+    };
+    gitlab
+        .respond(
+            Mock::given(method("PUT"))
+                .and(path(note(8, 200)))
+                .and(body_json(
+                    json!({ "body": "Missing test  \n#testing/integration #security/auth" }),
+                )),
+            ResponseTemplate::new(200).set_body_json(fixture("note_200_saved")),
+        )
+        .await;
+
+    let outcome = client.save(&change_request).await.unwrap();
+    let updated_at = "2024-11-09T08:00:00Z".parse().unwrap();
+    assert!(outcome == SaveOutcome::Saved { updated_at });
+
+    // The cache follows, so the next fetch needs no round trip.
+    let cached = client.fetch().await.unwrap();
+    let cached = find(&cached.change_requests, 200);
+    assert!(cached.tags.len() == 2);
+    assert!(cached.updated_at == updated_at);
+
+    // Saving the copy fetched before the edit would overwrite it.
+    gitlab
+        .respond(
+            Mock::given(method("GET")).and(path(note(8, 200))),
+            ResponseTemplate::new(200).set_body_json(fixture("note_200_saved")),
+        )
+        .await;
+    let outcome = client
+        .save(&ChangeRequest {
+            tags: vec![Tag::from("style")],
+            ..fetched
+        })
+        .await
+        .unwrap();
+    assert!(outcome == SaveOutcome::Conflict { updated_at });
+
+    let outcome = client.save(cached).await.unwrap();
+    assert!(outcome == SaveOutcome::Unchanged);
+}
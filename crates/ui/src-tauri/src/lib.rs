@@ -1,14 +1,106 @@
+use std::{env, path::Path, thread};
+
+use client::{
+    store::FileStore, GitlabClient, ReviewSource, Reviewers, Scope, Taxonomy, WebhookReceiver,
+};
+use log::*;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::net::TcpListener;
+
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 #[tauri::command]
 fn greet(name: &str) -> String {
     format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
+/// The taxonomy the UI normalizes tags to, the same as the webhook receiver.
+#[tauri::command]
+fn taxonomy(taxonomy: tauri::State<Option<Taxonomy>>) -> Option<Taxonomy> {
+    taxonomy.inner().clone()
+}
+
+/// Reads `REVIEWER_TAXONOMY`, a `.toml` or `.json` taxonomy, if set.
+fn load_taxonomy() -> Option<Taxonomy> {
+    let path = env::var("REVIEWER_TAXONOMY").ok()?;
+    Taxonomy::read(Path::new(&path))
+        .inspect_err(|error| error!("Failed to read the taxonomy {}: {}", path, error))
+        .ok()
+}
+
+/// Listens for GitLab webhooks when `GITLAB_WEBHOOK_SECRET` is set, emitting a
+/// `change-requests` event to the UI for every delivery. The receiver syncs
+/// its own cache first, so that it has notes to apply deliveries to, and the
+/// UI caches what it is sent.
+fn listen_for_webhooks(app: AppHandle, taxonomy: Option<Taxonomy>) {
+    let Ok(secret) = env::var("GITLAB_WEBHOOK_SECRET") else {
+        return;
+    };
+    let client = match webhook_client(&app) {
+        Ok(client) => match taxonomy {
+            Some(taxonomy) => client.with_taxonomy(taxonomy),
+            None => client,
+        },
+        Err(error) => {
+            error!("Not listening for webhooks: {}", error);
+            return;
+        }
+    };
+    let address = env::var("WEBHOOK_ADDRESS").unwrap_or_else(|_| "127.0.0.1:3000".to_string());
+
+    // The receiver is not `Send`, so it gets a runtime of its own.
+    thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .enable_time()
+            .build()
+            .expect("the webhook runtime should start");
+        let result = runtime.block_on(async {
+            if let Err(error) = client.fetch().await {
+                warn!("Failed to sync the webhook cache: {}", error);
+            }
+            let receiver = WebhookReceiver::new(client, secret).on_change(move |change_requests| {
+                if let Err(error) = app.emit("change-requests", change_requests) {
+                    warn!("Failed to forward change requests: {}", error);
+                }
+            });
+            let listener = TcpListener::bind(address).await?;
+            receiver.serve(listener).await
+        });
+        if let Err(error) = result {
+            error!("Webhook listener stopped: {}", error);
+        }
+    });
+}
+
+fn webhook_client(app: &AppHandle) -> Result<GitlabClient<FileStore>, String> {
+    let var = |name: &str| env::var(name).map_err(|_| format!("{} must be set", name));
+    let cache = app
+        .path()
+        .app_cache_dir()
+        .map_err(|error| format!("no cache directory: {}", error))?;
+    let Ok(scope) = var("GITLAB_PROJECT")?.parse::<Scope>();
+    let Ok(reviewers) = var("GITLAB_REVIEWERS")?.parse::<Reviewers>();
+    Ok(GitlabClient::new(
+        var("GITLAB_URL")?,
+        var("GITLAB_ACCESS_TOKEN")?,
+        scope,
+        reviewers,
+        FileStore::new(cache),
+    ))
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
+        .plugin(tauri_plugin_log::Builder::new().build())
         .plugin(tauri_plugin_shell::init())
-        .invoke_handler(tauri::generate_handler![greet])
+        .setup(|app| {
+            let taxonomy = load_taxonomy();
+            app.manage(taxonomy.clone());
+            listen_for_webhooks(app.handle().clone(), taxonomy);
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![greet, taxonomy])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
@@ -1,6 +1,14 @@
-use charts::SunburstChart;
-use client::GitlabClient;
+use std::time::Duration;
+
+use charts::{SunburstChart, Weighting};
+use chrono::NaiveDate;
+use client::{
+    store::IndexedDbStore, ChangeRequest, CommentLabel, DateRange, GitlabClient, Progress,
+    ReactionStats, ReviewSource, Reviewers, Scheduler, Scope, Severity, Size, SuggestionStats,
+    SyncPolicy, Taxonomy,
+};
 use dotenvy_macro::dotenv;
+use gloo_storage::{LocalStorage, Storage};
 use leptos::*;
 use log::*;
 use wasm_bindgen::prelude::*;
@@ -10,32 +18,184 @@ use crate::layout::Layout;
 #[component]
 pub fn Home() -> impl IntoView {
     let (author, set_author) = create_signal("all".to_string());
+    let (reviewer, set_reviewer) = create_signal("all".to_string());
+    let (project, set_project) = create_signal("all".to_string());
+    let (state, set_state) = create_signal("all".to_string());
+    let (label, set_label) = create_signal("all".to_string());
+    let (target_branch, set_target_branch) = create_signal("all".to_string());
+    let (draft, set_draft) = create_signal("all".to_string());
+    let (size, set_size) = create_signal("all".to_string());
+    let (feedback, set_feedback) = create_signal("all".to_string());
+    let (blocking, set_blocking) = create_signal("all".to_string());
+    let (comment_label, set_comment_label) = create_signal("all".to_string());
+    let (weighting, set_weighting) = create_signal(Weighting::default());
+    let (date_range, set_date_range) = create_signal(DateRange::default());
+    let (progress, set_progress) = create_signal(Progress::default());
+    let (sync_policy, set_sync_policy) =
+        create_signal(LocalStorage::get::<SyncPolicy>("sync_policy").unwrap_or_default());
 
-    let change_requests = create_resource(
-        || (),
-        |_| async move {
-            let access_token = dotenv!("GITLAB_ACCESS_TOKEN");
-            let project = dotenv!("GITLAB_PROJECT");
-            let client = GitlabClient::new(
-                access_token.to_string(),
-                project.to_string(),
-            );
-            client.fetch().await
-        },
-    );
+    create_effect(move |_| {
+        if let Err(error) = LocalStorage::set("sync_policy", sync_policy.get()) {
+            warn!("Failed to save the sync policy: {}", error);
+        }
+    });
 
-    let filtered_change_requests = create_memo(move |_| {
-        let change_requests = change_requests.get();
-        match (change_requests, author.get().as_str()) {
-            (Some(change_requests), "all") => Some(change_requests),
-            (Some(change_requests), author) => Some(
-                change_requests
-                    .into_iter()
-                    .filter(|value| value.author == author)
-                    .collect::<Vec<_>>(),
-            ),
-            _ => None,
+    let client = GitlabClient::new(
+        dotenv!("GITLAB_URL").to_string(),
+        dotenv!("GITLAB_ACCESS_TOKEN").to_string(),
+        dotenv!("GITLAB_PROJECT").parse::<Scope>().unwrap(),
+        dotenv!("GITLAB_REVIEWERS").parse::<Reviewers>().unwrap(),
+        IndexedDbStore::new("reviewer"),
+    )
+    .with_scheduler(Scheduler::default().on_progress(move |value| set_progress(value)));
+
+    let rebuild = create_action({
+        let client = client.clone();
+        move |_: &()| {
+            let client = client.clone().with_sync_policy(sync_policy.get_untracked());
+            async move { with_taxonomy(client).await.rebuild().await }
+        }
+    });
+
+    let change_requests = create_resource(move || (sync_policy.get(), rebuild.version().get()), {
+        let client = client.clone();
+        move |(sync_policy, _)| {
+            let client = client.clone().with_sync_policy(sync_policy);
+            async move { with_taxonomy(client).await.fetch().await }
+        }
+    });
+
+    // In the desktop app, change requests received by webhooks are pushed as
+    // events and take precedence over the synced ones. They are cached too,
+    // the receiver keeps a store of its own.
+    let (live_change_requests, set_live_change_requests) =
+        create_signal(Vec::<ChangeRequest>::new());
+    let on_change_requests = Closure::wrap(Box::new(move |event: JsValue| {
+        let change_requests = js_sys::Reflect::get(&event, &"payload".into())
+            .ok()
+            .and_then(|payload| js_sys::JSON::stringify(&payload).ok())
+            .and_then(|json| serde_json::from_str::<Vec<ChangeRequest>>(&String::from(json)).ok());
+        if let Some(change_requests) = change_requests {
+            let client = client.clone();
+            let cached = change_requests.clone();
+            spawn_local(async move {
+                let client = with_taxonomy(client).await;
+                if let Err(error) = client.cache_change_requests(&cached).await {
+                    warn!("Failed to cache pushed change requests: {}", error);
+                }
+            });
+            set_live_change_requests.update(|live| {
+                live.retain(|value| {
+                    !change_requests
+                        .iter()
+                        .any(|other| other.key() == value.key())
+                });
+                live.extend(change_requests);
+            });
+        }
+    }) as Box<dyn Fn(JsValue)>);
+    listen("change-requests", &on_change_requests);
+    on_change_requests.forget();
+
+    let all_change_requests = create_memo(move |_| {
+        let mut all = change_requests.get()?.ok()?.change_requests;
+        for change_request in live_change_requests.get() {
+            match all
+                .iter_mut()
+                .find(|value| value.key() == change_request.key())
+            {
+                Some(value) => *value = change_request,
+                None => all.push(change_request),
+            }
         }
+        Some(all)
+    });
+
+    let options = move |field: fn(ChangeRequest) -> String| {
+        let mut values = all_change_requests
+            .get()
+            .unwrap_or_default()
+            .into_iter()
+            .map(field)
+            .collect::<Vec<_>>();
+        values.sort();
+        values.dedup();
+        values
+    };
+    let reviewer_options = create_memo(move |_| options(|value| value.reviewer));
+    let project_options = create_memo(move |_| options(|value| value.project));
+    let target_branch_options =
+        create_memo(move |_| options(|value| value.merge_request.target_branch));
+    let label_options = create_memo(move |_| {
+        let mut labels = all_change_requests
+            .get()
+            .unwrap_or_default()
+            .into_iter()
+            .flat_map(|value| value.merge_request.labels)
+            .collect::<Vec<_>>();
+        labels.sort();
+        labels.dedup();
+        labels
+    });
+
+    let filtered_change_requests = create_memo(move |_| {
+        let change_requests = all_change_requests.get()?;
+        let author = author.get();
+        let reviewer = reviewer.get();
+        let project = project.get();
+        let state = state.get();
+        let label = label.get();
+        let target_branch = target_branch.get();
+        let draft = draft.get();
+        let size = size.get();
+        let feedback = feedback.get();
+        let blocking = blocking.get();
+        let comment_label = comment_label.get();
+        let date_range = date_range.get();
+        Some(
+            change_requests
+                .into_iter()
+                .filter(|value| author == "all" || value.author == author)
+                .filter(|value| reviewer == "all" || value.reviewer == reviewer)
+                .filter(|value| project == "all" || value.project == project)
+                .filter(|value| state == "all" || value.merge_request.state.to_string() == state)
+                .filter(|value| label == "all" || value.merge_request.labels.contains(&label))
+                .filter(|value| {
+                    target_branch == "all" || value.merge_request.target_branch == target_branch
+                })
+                .filter(|value| draft == "all" || value.merge_request.draft == (draft == "draft"))
+                .filter(|value| {
+                    size == "all"
+                        || value
+                            .merge_request
+                            .diff_stats
+                            .is_some_and(|stats| stats.size().to_string() == size)
+                })
+                .filter(|value| {
+                    let reactions = ReactionStats::of(value);
+                    match feedback.as_str() {
+                        "agreed" => reactions.agreed > 0,
+                        "disputed" => reactions.disputed > 0,
+                        _ => true,
+                    }
+                })
+                .filter(|value| match blocking.as_str() {
+                    "blocking" => value.severity.is_some_and(Severity::is_blocking),
+                    "non-blocking" => value
+                        .severity
+                        .is_some_and(|severity| !severity.is_blocking()),
+                    _ => true,
+                })
+                .filter(|value| {
+                    comment_label == "all"
+                        || value
+                            .conventional_comment
+                            .as_ref()
+                            .is_some_and(|comment| comment.label.to_string() == comment_label)
+                })
+                .filter(|value| value.created_within(&date_range))
+                .collect::<Vec<_>>(),
+        )
     });
 
     let on_click = Closure::wrap(Box::new(move |params| {
@@ -44,20 +204,30 @@ pub fn Home() -> impl IntoView {
             .dyn_into::<js_sys::JsString>()
             .expect("'on' should be a string");
         let name = name.as_string().unwrap();
-        let mut parts = name.split('/');
-        let merge_request_id = parts.next().unwrap();
-        let change_request_id = parts.next().unwrap();
-        let url = format!(
-            "https://gitlab.com/archipels-managed/connect-monorepo/-/merge_requests/{}/#note_{}",
-            merge_request_id, change_request_id
-        );
-        web_sys::window().unwrap().open_with_url(&url).unwrap();
+        let Some(change_request_id) = name
+            .split('/')
+            .nth(1)
+            .and_then(|value| value.parse::<u64>().ok())
+        else {
+            return;
+        };
+        let change_request = filtered_change_requests
+            .get_untracked()
+            .unwrap_or_default()
+            .into_iter()
+            .find(|value| value.id == change_request_id);
+        if let Some(change_request) = change_request {
+            web_sys::window()
+                .unwrap()
+                .open_with_url(&change_request.url)
+                .unwrap();
+        }
     }) as Box<dyn Fn(JsValue)>);
 
     create_effect(move |_| {
         info!("not ready :(");
         if let Some(change_requests) = filtered_change_requests.get() {
-            let chart = SunburstChart::new(change_requests);
+            let chart = SunburstChart::new(change_requests, weighting.get());
             chart.render("chart", &on_click);
         }
     });
@@ -86,10 +256,387 @@ pub fn Home() -> impl IntoView {
                         <option value="nlapointe-archipels">"Nico L"</option>
                         <option value="yohann-poli">"Yohann"</option>
                     </select>
+                    <label class="mr-2 ml-4 text-sm text-slate-600">Filter by reviewer</label>
+                    <select
+                        class="py-2 pr-8 pl-3 text-sm bg-white rounded border shadow-sm transition duration-300 appearance-none cursor-pointer focus:shadow-md focus:outline-none text-red placeholder:text-slate-400 text-slate-700 border-slate-200 ease hover:border-slate-400 focus:border-slate-400"
+                        on:change=move |ev| {
+                            let new_value = event_target_value(&ev);
+                            set_reviewer(new_value);
+                        }
+                        prop:value=move || reviewer.get().to_string()
+                    >
+                        <option value="all">"Everyone"</option>
+                        <For
+                            each=move || reviewer_options.get()
+                            key=|reviewer| reviewer.clone()
+                            children=move |reviewer| {
+                                view! { <option value=reviewer.clone()>{reviewer}</option> }
+                            }
+                        />
+                    </select>
+                    <label class="mr-2 ml-4 text-sm text-slate-600">Filter by project</label>
+                    <select
+                        class="py-2 pr-8 pl-3 text-sm bg-white rounded border shadow-sm transition duration-300 appearance-none cursor-pointer focus:shadow-md focus:outline-none text-red placeholder:text-slate-400 text-slate-700 border-slate-200 ease hover:border-slate-400 focus:border-slate-400"
+                        on:change=move |ev| {
+                            let new_value = event_target_value(&ev);
+                            set_project(new_value);
+                        }
+                        prop:value=move || project.get().to_string()
+                    >
+                        <option value="all">"All projects"</option>
+                        <For
+                            each=move || project_options.get()
+                            key=|project| project.clone()
+                            children=move |project| {
+                                view! { <option value=project.clone()>{project}</option> }
+                            }
+                        />
+                    </select>
+                    <label class="mr-2 ml-4 text-sm text-slate-600">State</label>
+                    <select
+                        class="py-2 pr-8 pl-3 text-sm bg-white rounded border shadow-sm transition duration-300 appearance-none cursor-pointer focus:shadow-md focus:outline-none text-red placeholder:text-slate-400 text-slate-700 border-slate-200 ease hover:border-slate-400 focus:border-slate-400"
+                        on:change=move |ev| set_state(event_target_value(&ev))
+                        prop:value=move || state.get()
+                    >
+                        <option value="all">"Any"</option>
+                        <option value="opened">"Open"</option>
+                        <option value="merged">"Merged"</option>
+                        <option value="closed">"Closed"</option>
+                    </select>
+                    <label class="mr-2 ml-4 text-sm text-slate-600">Label</label>
+                    <select
+                        class="py-2 pr-8 pl-3 text-sm bg-white rounded border shadow-sm transition duration-300 appearance-none cursor-pointer focus:shadow-md focus:outline-none text-red placeholder:text-slate-400 text-slate-700 border-slate-200 ease hover:border-slate-400 focus:border-slate-400"
+                        on:change=move |ev| set_label(event_target_value(&ev))
+                        prop:value=move || label.get()
+                    >
+                        <option value="all">"Any"</option>
+                        <For
+                            each=move || label_options.get()
+                            key=|label| label.clone()
+                            children=move |label| {
+                                view! { <option value=label.clone()>{label}</option> }
+                            }
+                        />
+                    </select>
+                    <label class="mr-2 ml-4 text-sm text-slate-600">Target branch</label>
+                    <select
+                        class="py-2 pr-8 pl-3 text-sm bg-white rounded border shadow-sm transition duration-300 appearance-none cursor-pointer focus:shadow-md focus:outline-none text-red placeholder:text-slate-400 text-slate-700 border-slate-200 ease hover:border-slate-400 focus:border-slate-400"
+                        on:change=move |ev| set_target_branch(event_target_value(&ev))
+                        prop:value=move || target_branch.get()
+                    >
+                        <option value="all">"Any"</option>
+                        <For
+                            each=move || target_branch_options.get()
+                            key=|branch| branch.clone()
+                            children=move |branch| {
+                                view! { <option value=branch.clone()>{branch}</option> }
+                            }
+                        />
+                    </select>
+                    <label class="mr-2 ml-4 text-sm text-slate-600">Draft</label>
+                    <select
+                        class="py-2 pr-8 pl-3 text-sm bg-white rounded border shadow-sm transition duration-300 appearance-none cursor-pointer focus:shadow-md focus:outline-none text-red placeholder:text-slate-400 text-slate-700 border-slate-200 ease hover:border-slate-400 focus:border-slate-400"
+                        on:change=move |ev| set_draft(event_target_value(&ev))
+                        prop:value=move || draft.get()
+                    >
+                        <option value="all">"Any"</option>
+                        <option value="ready">"Ready"</option>
+                        <option value="draft">"Draft"</option>
+                    </select>
+                    <label class="mr-2 ml-4 text-sm text-slate-600">Size</label>
+                    <select
+                        class="py-2 pr-8 pl-3 text-sm bg-white rounded border shadow-sm transition duration-300 appearance-none cursor-pointer focus:shadow-md focus:outline-none text-red placeholder:text-slate-400 text-slate-700 border-slate-200 ease hover:border-slate-400 focus:border-slate-400"
+                        on:change=move |ev| set_size(event_target_value(&ev))
+                        prop:value=move || size.get()
+                    >
+                        <option value="all">"Any"</option>
+                        {[Size::XS, Size::S, Size::M, Size::L, Size::XL]
+                            .into_iter()
+                            .map(|size| {
+                                view! { <option value=size.to_string()>{size.to_string()}</option> }
+                            })
+                            .collect_view()}
+                    </select>
+                    <label class="mr-2 ml-4 text-sm text-slate-600">Feedback</label>
+                    <select
+                        class="py-2 pr-8 pl-3 text-sm bg-white rounded border shadow-sm transition duration-300 appearance-none cursor-pointer focus:shadow-md focus:outline-none text-red placeholder:text-slate-400 text-slate-700 border-slate-200 ease hover:border-slate-400 focus:border-slate-400"
+                        on:change=move |ev| set_feedback(event_target_value(&ev))
+                        prop:value=move || feedback.get()
+                    >
+                        <option value="all">"Any"</option>
+                        <option value="agreed">"👍 Agreed"</option>
+                        <option value="disputed">"👎 Disputed"</option>
+                    </select>
+                    <label class="mr-2 ml-4 text-sm text-slate-600">Blocking</label>
+                    <select
+                        class="py-2 pr-8 pl-3 text-sm bg-white rounded border shadow-sm transition duration-300 appearance-none cursor-pointer focus:shadow-md focus:outline-none text-red placeholder:text-slate-400 text-slate-700 border-slate-200 ease hover:border-slate-400 focus:border-slate-400"
+                        on:change=move |ev| set_blocking(event_target_value(&ev))
+                        prop:value=move || blocking.get()
+                    >
+                        <option value="all">"Any"</option>
+                        <option value="blocking">"Blocking"</option>
+                        <option value="non-blocking">"Non-blocking"</option>
+                    </select>
+                    <label class="mr-2 ml-4 text-sm text-slate-600">Comment label</label>
+                    <select
+                        class="py-2 pr-8 pl-3 text-sm bg-white rounded border shadow-sm transition duration-300 appearance-none cursor-pointer focus:shadow-md focus:outline-none text-red placeholder:text-slate-400 text-slate-700 border-slate-200 ease hover:border-slate-400 focus:border-slate-400"
+                        on:change=move |ev| set_comment_label(event_target_value(&ev))
+                        prop:value=move || comment_label.get()
+                    >
+                        <option value="all">"Any"</option>
+                        {CommentLabel::ALL
+                            .into_iter()
+                            .map(|label| {
+                                view! { <option value=label.to_string()>{label.to_string()}</option> }
+                            })
+                            .collect_view()}
+                    </select>
+                    <label class="mr-2 ml-4 text-sm text-slate-600">Multiple tags</label>
+                    <select
+                        class="py-2 pr-8 pl-3 text-sm bg-white rounded border shadow-sm transition duration-300 appearance-none cursor-pointer focus:shadow-md focus:outline-none text-red placeholder:text-slate-400 text-slate-700 border-slate-200 ease hover:border-slate-400 focus:border-slate-400"
+                        on:change=move |ev| {
+                            set_weighting(
+                                match event_target_value(&ev).as_str() {
+                                    "fractional" => Weighting::Fractional,
+                                    _ => Weighting::Full,
+                                },
+                            )
+                        }
+                    >
+                        <option value="full">"Count under each"</option>
+                        <option value="fractional">"Split between them"</option>
+                    </select>
+                    <label class="mr-2 ml-4 text-sm text-slate-600">From</label>
+                    <input
+                        type="date"
+                        class="py-2 px-3 text-sm bg-white rounded border shadow-sm text-slate-700 border-slate-200"
+                        on:change=move |ev| {
+                            let from = event_target_value(&ev).parse().ok();
+                            set_date_range.update(|value| value.from = from);
+                        }
+                    />
+                    <label class="mr-2 ml-4 text-sm text-slate-600">To</label>
+                    <input
+                        type="date"
+                        class="py-2 px-3 text-sm bg-white rounded border shadow-sm text-slate-700 border-slate-200"
+                        on:change=move |ev| {
+                            let to = event_target_value(&ev).parse().ok();
+                            set_date_range.update(|value| value.to = to);
+                        }
+                    />
+                    <label class="mr-2 ml-4 text-sm text-slate-600">Sync since</label>
+                    <input
+                        type="date"
+                        class="py-2 px-3 text-sm bg-white rounded border shadow-sm text-slate-700 border-slate-200"
+                        prop:value=move || sync_policy.get().start.date_naive().to_string()
+                        on:change=move |ev| {
+                            if let Ok(start) = event_target_value(&ev).parse::<NaiveDate>() {
+                                set_sync_policy
+                                    .update(|value| {
+                                        value.start = start.and_hms_opt(0, 0, 0).unwrap().and_utc();
+                                    });
+                            }
+                        }
+                    />
+                    <label class="mr-2 ml-4 text-sm text-slate-600">Refresh every (min)</label>
+                    <input
+                        type="number"
+                        min="0"
+                        class="py-2 px-3 w-20 text-sm bg-white rounded border shadow-sm text-slate-700 border-slate-200"
+                        prop:value=move || (sync_policy.get().ttl.as_secs() / 60).to_string()
+                        on:change=move |ev| {
+                            if let Ok(minutes) = event_target_value(&ev).parse::<u64>() {
+                                set_sync_policy
+                                    .update(|value| value.ttl = Duration::from_secs(minutes * 60));
+                            }
+                        }
+                    />
+                    <label class="mr-2 ml-4 text-sm text-slate-600">Backfill (min)</label>
+                    <input
+                        type="number"
+                        min="0"
+                        class="py-2 px-3 w-20 text-sm bg-white rounded border shadow-sm text-slate-700 border-slate-200"
+                        prop:value=move || (sync_policy.get().backfill.as_secs() / 60).to_string()
+                        on:change=move |ev| {
+                            if let Ok(minutes) = event_target_value(&ev).parse::<u64>() {
+                                set_sync_policy
+                                    .update(|value| {
+                                        value.backfill = Duration::from_secs(minutes * 60);
+                                    });
+                            }
+                        }
+                    />
+                    <label class="mr-2 ml-4 text-sm text-slate-600">Full resync every (days)</label>
+                    <input
+                        type="number"
+                        min="0"
+                        placeholder="never"
+                        class="py-2 px-3 w-20 text-sm bg-white rounded border shadow-sm text-slate-700 border-slate-200"
+                        prop:value=move || {
+                            sync_policy
+                                .get()
+                                .full_resync_every
+                                .map(|every| (every.as_secs() / 86_400).to_string())
+                                .unwrap_or_default()
+                        }
+                        on:change=move |ev| {
+                            // Empty or zero never resyncs.
+                            let days = event_target_value(&ev).parse::<u64>().ok();
+                            set_sync_policy
+                                .update(|value| {
+                                    value.full_resync_every = days
+                                        .filter(|days| *days > 0)
+                                        .map(|days| Duration::from_secs(days * 86_400));
+                                });
+                        }
+                    />
+                    <button
+                        class="py-2 px-3 ml-4 text-sm bg-white rounded border shadow-sm transition duration-300 cursor-pointer text-slate-700 border-slate-200 hover:border-slate-400 disabled:opacity-50"
+                        disabled=move || rebuild.pending().get()
+                        on:click=move |_| rebuild.dispatch(())
+                    >
+                        "Rebuild cache"
+                    </button>
                 </div>
             }
         }>
+            {move || match change_requests.get() {
+                Some(Err(error)) => {
+                    view! {
+                        <div class="p-4 m-2 text-sm text-red-800 bg-red-50 rounded border border-red-200">
+                            {error.to_string()}
+                        </div>
+                    }
+                        .into_view()
+                }
+                Some(Ok(report)) if !report.errors.is_empty() => {
+                    view! {
+                        <div class="p-2 m-2 text-sm text-amber-800 bg-amber-50 rounded border border-amber-200">
+                            {format!(
+                                "{} merge request(s) could not be synced, showing partial results: {}",
+                                report.errors.len(),
+                                report.errors[0],
+                            )}
+                        </div>
+                    }
+                        .into_view()
+                }
+                None if progress.get().total > 0 => {
+                    view! {
+                        <div class="p-2 m-2 text-sm text-slate-600">
+                            {move || format!("{} MRs synced", progress.get())}
+                        </div>
+                    }
+                        .into_view()
+                }
+                _ => ().into_view(),
+            }}
+            {move || {
+                filtered_change_requests
+                    .get()
+                    .filter(|change_requests| !change_requests.is_empty())
+                    .map(|change_requests| {
+                        let resolvable = change_requests.iter().filter(|value| value.resolvable);
+                        let resolved = resolvable.clone().filter(|value| value.resolved).count();
+                        let replied = change_requests
+                            .iter()
+                            .filter(|value| value.author_replied)
+                            .count();
+                        let suggestions = SuggestionStats::by_category(&change_requests)
+                            .into_iter()
+                            .map(|(category, stats)| {
+                                format!(
+                                    "{} {}",
+                                    category.as_deref().unwrap_or("uncategorized"),
+                                    stats,
+                                )
+                            })
+                            .collect::<Vec<_>>();
+                        let disputed = ReactionStats::by_tag(&change_requests)
+                            .into_iter()
+                            .filter(|(_, stats)| stats.disputed > 0)
+                            .take(5)
+                            .map(|(tag, stats)| {
+                                format!("{} 👎{} 👍{}", tag, stats.disputed, stats.agreed)
+                            })
+                            .collect::<Vec<_>>();
+                        view! {
+                            <div class="px-2 text-sm text-slate-600">
+                                {format!(
+                                    "{}/{} threads resolved, {}/{} answered by the author",
+                                    resolved,
+                                    resolvable.count(),
+                                    replied,
+                                    change_requests.len(),
+                                )}
+                            </div>
+                            {(!suggestions.is_empty())
+                                .then(|| {
+                                    view! {
+                                        <div class="px-2 text-sm text-slate-600">
+                                            {format!("Suggestions applied: {}", suggestions.join(", "))}
+                                        </div>
+                                    }
+                                })}
+                            {(!disputed.is_empty())
+                                .then(|| {
+                                    view! {
+                                        <div class="px-2 text-sm text-slate-600">
+                                            {format!("Most disputed: {}", disputed.join(", "))}
+                                        </div>
+                                    }
+                                })}
+                        }
+                    })
+            }}
             <div class="flex-grow p-1" id="chart"></div>
         </Layout>
     }
 }
+
+/// Normalizes tags to the taxonomy of the desktop app, which its webhook
+/// receiver normalizes pushed change requests to as well.
+async fn with_taxonomy(client: GitlabClient<IndexedDbStore>) -> GitlabClient<IndexedDbStore> {
+    match invoke::<Taxonomy>("taxonomy").await {
+        Some(taxonomy) => client.with_taxonomy(taxonomy),
+        None => client,
+    }
+}
+
+/// A function of the Tauri API, `None` outside of the desktop app.
+fn tauri_function(path: [&str; 2]) -> Option<js_sys::Function> {
+    ["__TAURI__"]
+        .into_iter()
+        .chain(path)
+        .try_fold(JsValue::from(web_sys::window().unwrap()), |value, key| {
+            js_sys::Reflect::get(&value, &key.into())
+                .ok()
+                .filter(|value| !value.is_undefined())
+        })
+        .and_then(|function| function.dyn_into::<js_sys::Function>().ok())
+}
+
+/// Calls a Tauri command, `None` outside of the desktop app, when the command
+/// fails or when it returns nothing.
+async fn invoke<T: serde::de::DeserializeOwned>(command: &str) -> Option<T> {
+    let promise = tauri_function(["core", "invoke"])?
+        .call1(&JsValue::NULL, &command.into())
+        .ok()?
+        .dyn_into::<js_sys::Promise>()
+        .ok()?;
+    let value = wasm_bindgen_futures::JsFuture::from(promise)
+        .await
+        .inspect_err(|error| warn!("Failed to invoke {}: {:?}", command, error))
+        .ok()?;
+    let json = js_sys::JSON::stringify(&value).ok()?;
+    serde_json::from_str(&String::from(json)).ok()
+}
+
+/// Subscribes to a Tauri event, doing nothing outside of the desktop app.
+fn listen(event: &str, callback: &Closure<dyn Fn(JsValue)>) {
+    if let Some(listen) = tauri_function(["event", "listen"]) {
+        if let Err(error) = listen.call2(&JsValue::NULL, &event.into(), callback.as_ref()) {
+            warn!("Failed to listen to {}: {:?}", event, error);
+        }
+    }
+}